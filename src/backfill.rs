@@ -0,0 +1,122 @@
+use crate::client::IoBrokerClient;
+use crate::device_map::DeviceMap;
+use crate::mapper::{map_lamda_data, map_to_sensor_readings, MappingMode, QualityRules, TimestampStrategy, ValueScaling};
+use crate::models::model_iobroker::{IoBrokerResponse, IoBrokerValue};
+use crate::postgres_client::PostgresClient;
+use crate::register_map::RegisterMap;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+
+/// Sample interval and the longest distance between two rows that is not treated as a gap
+const LAMBDA_INTERVAL: Duration = Duration::seconds(30);
+const LAMBDA_MIN_GAP: Duration = Duration::seconds(90);
+const TEMPERATURE_INTERVAL: Duration = Duration::minutes(15);
+const TEMPERATURE_MIN_GAP: Duration = Duration::minutes(45);
+/// History adapters log on change, so values from before a gap are needed to know the state at its start
+const HISTORY_LOOKBACK: Duration = Duration::hours(6);
+
+/// Fills gaps in the `heatpump` and `sensor_reading` tables between `from` and `to`
+/// with samples rebuilt from the ioBroker history adapter
+#[allow(clippy::too_many_arguments)]
+pub async fn run_backfill(
+    io_broker: &IoBrokerClient,
+    database_client: &PostgresClient,
+    register_map: &RegisterMap,
+    device_map: &DeviceMap,
+    quality_rules: &QualityRules,
+    temperature_pattern: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<(), Box<dyn Error>> {
+    // Ages are meaningless for historic values, everything else is checked as usual
+    let quality_rules = QualityRules {
+        max_age: None,
+        ..quality_rules.clone()
+    };
+
+    let gaps = database_client.find_gaps("heatpump", from, to, LAMBDA_MIN_GAP).await?;
+    println!("Found {} gaps in heatpump between {} and {}", gaps.len(), from, to);
+    for gap in gaps {
+        let (gap_start, gap_end) = (gap.gap_start.to_utc(), gap.gap_end.to_utc());
+        let history = io_broker
+            .fetch_history(&register_map.state_ids(), gap_start - HISTORY_LOOKBACK, gap_end)
+            .await?;
+
+        let mut inserted = 0;
+        // Not every state is necessarily logged by the history adapter, so store whatever is there
+        for snapshot in snapshots(&history, gap_start, gap_end, LAMBDA_INTERVAL) {
+            match map_lamda_data(&snapshot, register_map, &quality_rules, MappingMode::Tolerant, ValueScaling::PreScaled, TimestampStrategy::Newest) {
+                Ok(data) if data.event_timestamp > gap_start => {
+                    inserted += database_client.write_lambda_data(data).await?;
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Skipping incomplete history sample: {}", e),
+            }
+        }
+        println!("Backfilled {} heatpump rows between {} and {}", inserted, gap_start, gap_end);
+    }
+
+    let temperature_ids: Vec<String> = io_broker
+        .fetch_data(format!("/states?filter={}", temperature_pattern))
+        .await?
+        .into_keys()
+        .collect();
+    let temperature_ids: Vec<&str> = temperature_ids.iter().map(String::as_str).collect();
+
+    // Snapshots repeat the same states, so devices without a name are reported once at the end
+    let mut unmatched_devices = BTreeSet::new();
+    let gaps = database_client.find_gaps("sensor_reading", from, to, TEMPERATURE_MIN_GAP).await?;
+    println!("Found {} gaps in sensor_reading between {} and {}", gaps.len(), from, to);
+    for gap in gaps {
+        let (gap_start, gap_end) = (gap.gap_start.to_utc(), gap.gap_end.to_utc());
+        let history = io_broker
+            .fetch_history(&temperature_ids, gap_start - HISTORY_LOOKBACK, gap_end)
+            .await?;
+
+        let mut inserted = 0;
+        for snapshot in snapshots(&history, gap_start, gap_end, TEMPERATURE_INTERVAL) {
+            let sample = map_to_sensor_readings(snapshot, device_map, TimestampStrategy::Newest)?;
+            unmatched_devices.extend(sample.unmatched_devices.iter().cloned());
+            if !sample.readings.is_empty() && sample.event_timestamp > gap_start {
+                inserted += database_client.write_temperature_data(sample).await?;
+            }
+        }
+        println!("Backfilled {} temperature rows between {} and {}", inserted, gap_start, gap_end);
+    }
+    if !unmatched_devices.is_empty() {
+        let unmatched_devices: Vec<String> = unmatched_devices.into_iter().collect();
+        eprintln!("No device found for temperature states: {}", unmatched_devices.join(", "));
+    }
+
+    Ok(())
+}
+
+// State of every logged ID at each interval step inside the gap, using the last value
+// logged at or before that step. Steps without new values map to the same event
+// timestamp and are dropped as duplicates on insert.
+fn snapshots(
+    history: &HashMap<String, Vec<IoBrokerValue>>,
+    gap_start: DateTime<Utc>,
+    gap_end: DateTime<Utc>,
+    interval: Duration,
+) -> Vec<IoBrokerResponse> {
+    let mut snapshots = Vec::new();
+    let mut step = gap_start + interval;
+    while step < gap_end {
+        let cutoff = step.timestamp_millis();
+        let snapshot: IoBrokerResponse = history
+            .iter()
+            .filter_map(|(id, values)| {
+                values
+                    .iter()
+                    .take_while(|value| value.ts <= cutoff)
+                    .last()
+                    .map(|value| (id.clone(), value.clone()))
+            })
+            .collect();
+        snapshots.push(snapshot);
+        step += interval;
+    }
+    snapshots
+}
@@ -0,0 +1,216 @@
+use crate::models::{model_charging::ChargingSession, model_pv::PvData};
+use chrono::{DateTime, Duration, Utc};
+
+/// Thresholds that decide when the wallbox counts as charging
+#[derive(Debug, Clone)]
+pub struct ChargingSessionRules {
+    /// Wallbox power from which a sample counts as charging, in W
+    pub min_power: i64,
+    /// How long the power has to stay below `min_power` before the session ends,
+    /// so pauses of PV surplus charging do not split a session
+    pub idle_timeout: Duration,
+    /// Samples further apart than this are not integrated, the open session ends at the last sample
+    pub max_gap: Duration,
+}
+
+impl Default for ChargingSessionRules {
+    fn default() -> Self {
+        Self {
+            min_power: 100,
+            idle_timeout: Duration::minutes(15),
+            max_gap: Duration::minutes(10),
+        }
+    }
+}
+
+struct OpenSession {
+    session: ChargingSession,
+    idle_since: Option<DateTime<Utc>>,
+}
+
+/// Builds charging sessions out of consecutive PV samples
+pub struct ChargingSessionDetector {
+    rules: ChargingSessionRules,
+    last: Option<PvData>,
+    open: Option<OpenSession>,
+}
+
+impl ChargingSessionDetector {
+    pub fn new(rules: ChargingSessionRules) -> Self {
+        Self { rules, last: None, open: None }
+    }
+
+    /// Feeds the next sample, returns the session it completed if any
+    pub fn push(&mut self, sample: PvData) -> Option<ChargingSession> {
+        let mut finished = None;
+        if let Some(last) = &self.last {
+            // Source timestamps repeat when ioBroker did not update the states since the last fetch
+            if sample.event_timestamp <= last.event_timestamp {
+                return None;
+            }
+            let elapsed = sample.event_timestamp - last.event_timestamp;
+            if elapsed > self.rules.max_gap {
+                finished = self.close(last.event_timestamp);
+            } else if let Some(open) = &mut self.open {
+                add_energy(&mut open.session, last, elapsed);
+            }
+        }
+
+        let charging = sample.wallbox >= self.rules.min_power;
+        match (&mut self.open, charging) {
+            (Some(open), true) => {
+                open.idle_since = None;
+                open.session.peak_power = open.session.peak_power.max(sample.wallbox);
+            }
+            (Some(open), false) => {
+                let idle_since = *open.idle_since.get_or_insert(sample.event_timestamp);
+                if sample.event_timestamp - idle_since >= self.rules.idle_timeout {
+                    finished = self.close(sample.event_timestamp);
+                }
+            }
+            (None, true) => {
+                self.open = Some(OpenSession {
+                    session: ChargingSession {
+                        started_at: sample.event_timestamp,
+                        ended_at: sample.event_timestamp,
+                        energy_wh: 0.0,
+                        pv_energy_wh: 0.0,
+                        battery_energy_wh: 0.0,
+                        grid_energy_wh: 0.0,
+                        peak_power: sample.wallbox,
+                    },
+                    idle_since: None,
+                });
+            }
+            (None, false) => {}
+        }
+        self.last = Some(sample);
+        finished
+    }
+
+    /// Ends the open session at the last sample, used on shutdown so the energy charged so far is kept
+    pub fn finish(&mut self) -> Option<ChargingSession> {
+        let end = self.last.as_ref()?.event_timestamp;
+        self.close(end)
+    }
+
+    // The session ends when the power dropped, not when the idle timeout ran out
+    fn close(&mut self, end: DateTime<Utc>) -> Option<ChargingSession> {
+        let open = self.open.take()?;
+        let mut session = open.session;
+        session.ended_at = open.idle_since.unwrap_or(end);
+        Some(session)
+    }
+}
+
+// Adds the energy of `sample` held for `elapsed`. PV covers the house first, so only its surplus
+// reaches the wallbox, then a discharging battery, the rest is drawn from the grid.
+fn add_energy(session: &mut ChargingSession, sample: &PvData, elapsed: Duration) {
+    let hours = elapsed.num_milliseconds() as f64 / 3_600_000.0;
+    let wallbox = sample.wallbox.max(0);
+    let pv = (sample.pv - sample.home).clamp(0, wallbox);
+    let battery_discharge = (-sample.battery_power).max(0);
+    let battery = (battery_discharge - (sample.home - sample.pv).max(0)).clamp(0, wallbox - pv);
+    let grid = wallbox - pv - battery;
+
+    session.energy_wh += wallbox as f64 * hours;
+    session.pv_energy_wh += pv as f64 * hours;
+    session.battery_energy_wh += battery as f64 * hours;
+    session.grid_energy_wh += grid as f64 * hours;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(minute)
+    }
+
+    fn sample(minute: i64, wallbox: i64, pv: i64, home: i64, battery_power: i64) -> PvData {
+        PvData {
+            event_timestamp: at(minute),
+            clock_skew_ms: 0,
+            battery_power,
+            battery_percentage: 50.0,
+            grid: 0,
+            home,
+            pv,
+            wallbox,
+        }
+    }
+
+    // Energy of one session charged for a minute from a single sample
+    fn one_minute(wallbox: i64, pv: i64, home: i64, battery_power: i64) -> ChargingSession {
+        let mut detector = ChargingSessionDetector::new(ChargingSessionRules::default());
+        assert!(detector.push(sample(0, wallbox, pv, home, battery_power)).is_none());
+        assert!(detector.push(sample(1, wallbox, pv, home, battery_power)).is_none());
+        detector.finish().unwrap()
+    }
+
+    #[test]
+    fn session_ends_when_the_power_dropped_after_the_idle_timeout() {
+        let mut detector = ChargingSessionDetector::new(ChargingSessionRules::default());
+        assert!(detector.push(sample(0, 3000, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(1, 6000, 0, 0, 0)).is_none());
+        for minute in 2..17 {
+            assert!(detector.push(sample(minute, 0, 0, 0, 0)).is_none());
+        }
+        let session = detector.push(sample(17, 0, 0, 0, 0)).unwrap();
+        assert_eq!((session.started_at, session.ended_at), (at(0), at(2)));
+        assert_eq!(session.peak_power, 6000);
+        assert!((session.energy_wh - 150.0).abs() < 1e-9);
+        assert!(detector.finish().is_none());
+    }
+
+    #[test]
+    fn short_pauses_do_not_split_a_session() {
+        let mut detector = ChargingSessionDetector::new(ChargingSessionRules::default());
+        assert!(detector.push(sample(0, 3000, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(1, 50, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(10, 3000, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(11, 3000, 0, 0, 0)).is_none());
+        let session = detector.finish().unwrap();
+        assert_eq!((session.started_at, session.ended_at), (at(0), at(11)));
+    }
+
+    #[test]
+    fn gaps_end_the_session_at_the_last_sample() {
+        let mut detector = ChargingSessionDetector::new(ChargingSessionRules::default());
+        assert!(detector.push(sample(0, 3000, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(1, 3000, 0, 0, 0)).is_none());
+        let session = detector.push(sample(30, 3000, 0, 0, 0)).unwrap();
+        assert_eq!((session.started_at, session.ended_at), (at(0), at(1)));
+        assert!((session.energy_wh - 50.0).abs() < 1e-9);
+        // The sample after the gap starts the next session
+        assert_eq!(detector.finish().unwrap().started_at, at(30));
+    }
+
+    #[test]
+    fn repeated_timestamps_are_skipped() {
+        let mut detector = ChargingSessionDetector::new(ChargingSessionRules::default());
+        assert!(detector.push(sample(0, 3000, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(1, 3000, 0, 0, 0)).is_none());
+        assert!(detector.push(sample(1, 3000, 0, 0, 0)).is_none());
+        assert!((detector.finish().unwrap().energy_wh - 50.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn energy_is_split_into_pv_surplus_battery_and_grid() {
+        // PV surplus after the house covers the whole wallbox
+        let session = one_minute(3000, 5000, 1000, 0);
+        assert_eq!((session.pv_energy_wh, session.battery_energy_wh, session.grid_energy_wh), (50.0, 0.0, 0.0));
+
+        // No surplus, the discharging battery covers 2000 W and the grid the rest
+        let session = one_minute(3000, 1000, 1000, -2000);
+        assert_eq!((session.pv_energy_wh, session.battery_energy_wh, session.grid_energy_wh), (0.0, 2000.0 / 60.0, 1000.0 / 60.0));
+
+        // The battery covers the house deficit first
+        let session = one_minute(2000, 500, 1000, -1000);
+        assert_eq!((session.pv_energy_wh, session.battery_energy_wh, session.grid_energy_wh), (0.0, 500.0 / 60.0, 1500.0 / 60.0));
+
+        // A charging battery adds nothing
+        let session = one_minute(2000, 3000, 1000, 1000);
+        assert_eq!((session.pv_energy_wh, session.battery_energy_wh, session.grid_energy_wh), (2000.0 / 60.0, 0.0, 0.0));
+    }
+}
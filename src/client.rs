@@ -1,15 +1,26 @@
-use crate::models::model_iobroker::IoBrokerResponse;
-use reqwest::{self, Client, ClientBuilder};
+use crate::models::model_iobroker::{IoBrokerBulkValue, IoBrokerHistory, IoBrokerResponse, IoBrokerValue};
+use crate::retry::{CircuitBreaker, CircuitBreakerConfig, RetryPolicy};
+use serde::de::DeserializeOwned;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use reqwest::{self, Certificate, Client, ClientBuilder, Identity};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
 use std::fmt;
+use std::path::PathBuf;
 use std::time::Duration;
 use tokio::time::sleep;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ClientError {
     NetworkError(reqwest::Error),
     ParseError(String),  // Changed to store more detailed error info
     HttpError(reqwest::StatusCode),
     TimeoutError,
+    ConfigError(String),
+    CircuitOpenError(Duration),
 }
 
 impl fmt::Display for ClientError {
@@ -19,67 +30,295 @@ impl fmt::Display for ClientError {
             ClientError::ParseError(e) => write!(f, "Parse error: {}", e),
             ClientError::HttpError(code) => write!(f, "HTTP error: {}", code),
             ClientError::TimeoutError => write!(f, "Request timed out"),
+            ClientError::ConfigError(e) => write!(f, "Configuration error: {}", e),
+            ClientError::CircuitOpenError(retry_in) => {
+                write!(f, "ioBroker unavailable, circuit open for another {}s", retry_in.as_secs())
+            }
+        }
+    }
+}
+
+impl ClientError {
+    /// Whether the same request might succeed when sent again
+    fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::NetworkError(e) => !e.is_builder() && !e.is_decode(),
+            ClientError::TimeoutError => true,
+            ClientError::HttpError(status) => {
+                status.is_server_error()
+                    || *status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            ClientError::ParseError(_) | ClientError::ConfigError(_) | ClientError::CircuitOpenError(_) => false,
         }
     }
 }
 
 impl std::error::Error for ClientError {}
 
+/// Credentials sent with every request to ioBroker
+#[derive(Debug, Clone, Default)]
+pub enum IoBrokerAuth {
+    #[default]
+    None,
+    Basic { user: String, password: String },
+    Bearer(String),
+}
+
+impl IoBrokerAuth {
+    /// Value of the `Authorization` header, if any
+    pub fn header_value(&self) -> Option<String> {
+        match self {
+            IoBrokerAuth::None => None,
+            IoBrokerAuth::Basic { user, password } => {
+                Some(format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password))))
+            }
+            IoBrokerAuth::Bearer(token) => Some(format!("Bearer {}", token)),
+        }
+    }
+}
+
+// PEM encoded client certificate and key
+type PemIdentity = (Vec<u8>, Vec<u8>);
+
+/// TLS settings for HTTPS connections to ioBroker, all files are PEM encoded
+#[derive(Debug, Clone, Default)]
+pub struct IoBrokerTls {
+    /// Additional CA certificates trusted next to the system roots
+    pub ca_bundle: Option<PathBuf>,
+    /// Client certificate and its PKCS#8 key for mutual TLS
+    pub client_certificate: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Self-signed server certificate that is trusted exclusively, the system roots are ignored
+    pub pinned_certificate: Option<PathBuf>,
+}
+
+impl IoBrokerTls {
+    fn configure(&self, mut builder: ClientBuilder) -> Result<ClientBuilder, ClientError> {
+        for pem in self.trusted_certificates()? {
+            let certificate = Certificate::from_pem(&pem)
+                .map_err(|e| ClientError::ConfigError(format!("Invalid certificate: {}", e)))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        if self.pinned_certificate.is_some() {
+            builder = builder.tls_built_in_root_certs(false);
+        }
+        if let Some((certificate, key)) = self.client_identity()? {
+            let identity = Identity::from_pkcs8_pem(&certificate, &key)
+                .map_err(|e| ClientError::ConfigError(format!("Invalid client certificate: {}", e)))?;
+            builder = builder.identity(identity);
+        }
+        Ok(builder)
+    }
+
+    /// Same settings for connections that are not made through reqwest, like the socket.io subscription
+    pub fn tls_connector(&self) -> Result<native_tls::TlsConnector, ClientError> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for pem in self.trusted_certificates()? {
+            let certificate = native_tls::Certificate::from_pem(&pem)
+                .map_err(|e| ClientError::ConfigError(format!("Invalid certificate: {}", e)))?;
+            builder.add_root_certificate(certificate);
+        }
+        if self.pinned_certificate.is_some() {
+            builder.disable_built_in_roots(true);
+        }
+        if let Some((certificate, key)) = self.client_identity()? {
+            let identity = native_tls::Identity::from_pkcs8(&certificate, &key)
+                .map_err(|e| ClientError::ConfigError(format!("Invalid client certificate: {}", e)))?;
+            builder.identity(identity);
+        }
+        builder
+            .build()
+            .map_err(|e| ClientError::ConfigError(format!("TLS setup failed: {}", e)))
+    }
+
+    fn trusted_certificates(&self) -> Result<Vec<Vec<u8>>, ClientError> {
+        // A CA bundle would be trusted next to the pinned certificate and defeat the pinning
+        if self.ca_bundle.is_some() && self.pinned_certificate.is_some() {
+            return Err(ClientError::ConfigError(String::from(
+                "A pinned certificate can not be combined with a CA bundle",
+            )));
+        }
+        let mut certificates = Vec::new();
+        for path in [&self.ca_bundle, &self.pinned_certificate].into_iter().flatten() {
+            certificates.extend(split_pem_bundle(&read_file(path)?));
+        }
+        Ok(certificates)
+    }
+
+    fn client_identity(&self) -> Result<Option<PemIdentity>, ClientError> {
+        match (&self.client_certificate, &self.client_key) {
+            (Some(certificate), Some(key)) => Ok(Some((read_file(certificate)?, read_file(key)?))),
+            (None, None) => Ok(None),
+            _ => Err(ClientError::ConfigError(String::from(
+                "Client certificate and key have to be configured together",
+            ))),
+        }
+    }
+}
+
+fn read_file(path: &PathBuf) -> Result<Vec<u8>, ClientError> {
+    std::fs::read(path).map_err(|e| ClientError::ConfigError(format!("{}: {}", path.display(), e)))
+}
+
+// Logged values of each state, oldest first. The backfill takes the last value logged up to each step,
+// so entries of the same state are merged and sorted, whatever order the history adapter returns.
+fn history_by_state(entries: Vec<IoBrokerHistory>) -> HashMap<String, Vec<IoBrokerValue>> {
+    let mut values: HashMap<String, Vec<IoBrokerValue>> = HashMap::new();
+    for entry in entries {
+        values.entry(entry.target.clone()).or_default().extend(entry.into_values());
+    }
+    for state_values in values.values_mut() {
+        state_values.sort_by_key(|value| value.ts);
+    }
+    values
+}
+
+// native-tls only parses a single certificate per PEM, so bundles are split up front
+fn split_pem_bundle(pem: &[u8]) -> Vec<Vec<u8>> {
+    const END: &str = "-----END CERTIFICATE-----";
+    String::from_utf8_lossy(pem)
+        .split_inclusive(END)
+        .filter(|block| block.contains(END))
+        .map(|block| block.trim().as_bytes().to_vec())
+        .collect()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IoBrokerClientOptions {
+    pub auth: IoBrokerAuth,
+    pub tls: IoBrokerTls,
+    pub retry: RetryPolicy,
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+/// State IDs joined into one request path stay below this length, Node rejects requests with more than 16 KB of headers
+const MAX_ID_LIST_LENGTH: usize = 4096;
+
 #[derive(Clone)]
 pub struct IoBrokerClient {
     client: Client,
     base_url: String,
-    max_retries: u32,
+    retry: RetryPolicy,
+    circuit_breaker: CircuitBreaker,
 }
 
 impl IoBrokerClient {
-    pub fn new(base_url: String) -> Result<Self, ClientError> {
-        let client = ClientBuilder::new()
+    pub fn new(base_url: String, options: &IoBrokerClientOptions) -> Result<Self, ClientError> {
+        let mut headers = HeaderMap::new();
+        if let Some(authorization) = options.auth.header_value() {
+            let mut value = HeaderValue::from_str(&authorization)
+                .map_err(|e| ClientError::ConfigError(format!("Invalid credentials: {}", e)))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let builder = ClientBuilder::new()
             .timeout(Duration::from_secs(30))
             .connect_timeout(Duration::from_secs(10))
             .pool_idle_timeout(Duration::from_secs(90))
             .pool_max_idle_per_host(5)
+            .default_headers(headers);
+        let client = options
+            .tls
+            .configure(builder)?
             .build()
             .map_err(ClientError::NetworkError)?;
 
         Ok(Self {
             client,
+            circuit_breaker: CircuitBreaker::new(base_url.clone(), options.circuit_breaker.clone()),
             base_url,
-            max_retries: 1,
+            retry: options.retry.clone(),
         })
     }
 
     pub async fn fetch_data(&self, path: String) -> Result<IoBrokerResponse, ClientError> {
-        let mut attempts = 0;
-        let mut last_error = None;
+        self.fetch(path).await
+    }
+
+    /// Fetches exactly the given state IDs with the simple-api getBulk endpoint,
+    /// states that do not exist in ioBroker are left out
+    pub async fn fetch_states(&self, ids: &[&str]) -> Result<IoBrokerResponse, ClientError> {
+        let mut response = IoBrokerResponse::new();
+        for id_list in id_lists(ids) {
+            let values: Vec<IoBrokerBulkValue> = self.fetch(format!("/getBulk/{}", id_list)).await?;
+            response.extend(
+                values
+                    .into_iter()
+                    .filter(|value| value.ts.is_some())
+                    .map(|value| (value.id.clone(), value.into())),
+            );
+        }
+        Ok(response)
+    }
+
+    /// Logged values of the given states between `from` and `to`, oldest first, queried from the
+    /// default history adapter through the simple-api query endpoint
+    pub async fn fetch_history(
+        &self,
+        ids: &[&str],
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<HashMap<String, Vec<IoBrokerValue>>, ClientError> {
+        let mut entries = Vec::new();
+        for id_list in id_lists(ids) {
+            let path = format!(
+                "/query/{}?dateFrom={}&dateTo={}&noHistory=false&aggregate=none",
+                id_list,
+                from.to_rfc3339_opts(SecondsFormat::Millis, true),
+                to.to_rfc3339_opts(SecondsFormat::Millis, true),
+            );
+            let history: Vec<IoBrokerHistory> = self.fetch(path).await?;
+            entries.extend(history);
+        }
+        Ok(history_by_state(entries))
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, path: String) -> Result<T, ClientError> {
+        self.circuit_breaker
+            .allow_request()
+            .map_err(ClientError::CircuitOpenError)?;
         println!("Fetching data from: {}", path);
-        
-        while attempts < self.max_retries {
+
+        let mut retry = 0;
+        loop {
             match self.try_fetch_data(path.clone()).await {
-                Ok(data) => return Ok(data),
-                Err(e) if attempts < self.max_retries => {
-                    sleep(Duration::from_secs(2u64.pow(attempts))).await;
-                    last_error = Some(e);
-                    attempts += 1;
+                Ok(data) => {
+                    self.circuit_breaker.record_success();
+                    return Ok(data);
+                }
+                Err(e) if e.is_retryable() && retry < self.retry.max_retries => {
+                    let backoff = self.retry.backoff(retry);
+                    eprintln!("Request to {} failed: {}, retrying in {:?}", path, e, backoff);
+                    sleep(backoff).await;
+                    retry += 1;
                 }
                 Err(e) => {
-                    last_error = Some(e);
-                    attempts += 1;
+                    // Only failures that point to ioBroker being unavailable count towards the breaker,
+                    // any other answer shows it is reachable and ends a half-open trial
+                    if e.is_retryable() {
+                        self.circuit_breaker.record_failure();
+                    } else {
+                        self.circuit_breaker.record_success();
+                    }
+                    return Err(e);
                 }
             }
         }
-
-        Err(last_error.unwrap_or(ClientError::TimeoutError))
     }
 
-    async fn try_fetch_data(&self,path:String) -> Result<IoBrokerResponse, ClientError> {
+    async fn try_fetch_data<T: DeserializeOwned>(&self,path:String) -> Result<T, ClientError> {
         let url = format!("{}{}", self.base_url, path);
         let response = self
             .client
             .get(url)
             .send()
             .await
-            .map_err(ClientError::NetworkError)?;
+            .map_err(|e| match e.is_timeout() {
+                true => ClientError::TimeoutError,
+                false => ClientError::NetworkError(e),
+            })?;
 
         if !response.status().is_success() {
             return Err(ClientError::HttpError(response.status()));
@@ -90,4 +329,75 @@ impl IoBrokerClient {
             ClientError::ParseError(format!("Parse error: {}. Response: {}", e, text))
         })
     }
-}
\ No newline at end of file
+}
+
+// Splits the state IDs into comma separated lists that each fit into a request path
+fn id_lists(ids: &[&str]) -> Vec<String> {
+    let mut lists = Vec::new();
+    let mut list = String::new();
+    for id in ids {
+        if !list.is_empty() && list.len() + 1 + id.len() > MAX_ID_LIST_LENGTH {
+            lists.push(std::mem::take(&mut list));
+        }
+        if !list.is_empty() {
+            list.push(',');
+        }
+        list.push_str(id);
+    }
+    if !list.is_empty() {
+        lists.push(list);
+    }
+    lists
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_lists_split_before_the_length_limit() {
+        let ids: Vec<String> = (0..1000).map(|n| format!("modbus.0.holdingRegisters.{}_State", 40000 + n)).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let lists = id_lists(&ids);
+        assert!(lists.len() > 1);
+        assert!(lists.iter().all(|list| list.len() <= MAX_ID_LIST_LENGTH));
+        assert_eq!(lists.join(","), ids.join(","));
+    }
+
+    #[test]
+    fn id_lists_keep_short_lists_and_oversized_ids_whole() {
+        assert_eq!(id_lists(&["a", "b"]), ["a,b"]);
+        assert!(id_lists(&[]).is_empty());
+        let long = "x".repeat(MAX_ID_LIST_LENGTH + 1);
+        assert_eq!(id_lists(&["a", &long, "b"]), ["a", long.as_str(), "b"]);
+    }
+
+    #[test]
+    fn history_is_merged_and_sorted_per_state() {
+        let entries: Vec<IoBrokerHistory> = serde_json::from_str(
+            r#"[
+                {"target": "a", "datapoints": [[3, 3000], [1, 1000]]},
+                {"target": "b", "datapoints": [["on", 2000]]},
+                {"target": "a", "datapoints": [[2, 2000]]}
+            ]"#,
+        )
+        .unwrap();
+        let history = history_by_state(entries);
+        let logged = |id: &str| -> Vec<(String, i64)> {
+            history[id].iter().map(|value| (value.val.clone(), value.ts)).collect()
+        };
+        assert_eq!(logged("a"), [("1".into(), 1000), ("2".into(), 2000), ("3".into(), 3000)]);
+        assert_eq!(logged("b"), [("on".into(), 2000)]);
+    }
+
+    #[test]
+    fn tls_rejects_a_pinned_certificate_with_a_ca_bundle() {
+        let tls = IoBrokerTls {
+            ca_bundle: Some(PathBuf::from("ca.pem")),
+            pinned_certificate: Some(PathBuf::from("iobroker.pem")),
+            ..IoBrokerTls::default()
+        };
+        assert!(matches!(tls.trusted_certificates(), Err(ClientError::ConfigError(_))));
+        assert!(tls.tls_connector().is_err());
+    }
+}
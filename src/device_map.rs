@@ -0,0 +1,174 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+/// Naming shipped with the binary, used when no TEMPERATURE_DEVICE_MAP is configured
+const BUNDLED_MAP: &str = include_str!("../device_map.toml");
+
+#[derive(Debug)]
+pub enum DeviceMapError {
+    ReadError(String, std::io::Error),
+    ParseError(toml::de::Error),
+    InvalidPattern(regex::Error),
+}
+
+impl fmt::Display for DeviceMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceMapError::ReadError(path, e) => write!(f, "Error reading device map {}: {}", path, e),
+            DeviceMapError::ParseError(e) => write!(f, "Error parsing device map: {}", e),
+            DeviceMapError::InvalidPattern(e) => write!(f, "Invalid device pattern: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DeviceMapError {}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceMapFile {
+    pattern: String,
+    /// Quantities stored from sensor payloads, all numeric ones if omitted
+    quantities: Option<HashSet<String>>,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+    #[serde(default)]
+    devices: HashMap<String, DeviceInfo>,
+}
+
+/// Where a device is placed, every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceInfo {
+    pub name: Option<String>,
+    pub room: Option<String>,
+    pub floor: Option<String>,
+}
+
+/// Device a sensor state ID or MQTT topic belongs to
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id: String,
+    pub info: DeviceInfo,
+}
+
+/// Mapping from state IDs and MQTT topics to the sensor devices they report for
+#[derive(Debug, Clone)]
+pub struct DeviceMap {
+    pattern: Regex,
+    quantities: Option<HashSet<String>>,
+    aliases: HashMap<String, String>,
+    devices: HashMap<String, DeviceInfo>,
+}
+
+impl DeviceMap {
+    /// The naming bundled with the binary, the part after the first underscore is the device
+    pub fn bundled() -> Result<Self, DeviceMapError> {
+        Self::from_toml(BUNDLED_MAP)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DeviceMapError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| DeviceMapError::ReadError(path.display().to_string(), e))?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, DeviceMapError> {
+        let file: DeviceMapFile = toml::from_str(content).map_err(DeviceMapError::ParseError)?;
+        Ok(Self {
+            pattern: Regex::new(&file.pattern).map_err(DeviceMapError::InvalidPattern)?,
+            quantities: file.quantities,
+            aliases: file.aliases,
+            devices: file.devices,
+        })
+    }
+
+    /// Whether a quantity of a sensor payload is stored
+    pub fn accepts(&self, quantity: &str) -> bool {
+        self.quantities.as_ref().is_none_or(|quantities| quantities.contains(quantity))
+    }
+
+    /// Device of a state ID or topic, `None` if it has no alias and does not match the pattern
+    pub fn resolve(&self, source: &str) -> Option<Device> {
+        let id = match self.aliases.get(source) {
+            Some(id) => id.clone(),
+            None => {
+                let captures = self.pattern.captures(source)?;
+                let id = captures
+                    .name("device")
+                    .or_else(|| captures.get(1))
+                    .or_else(|| captures.get(0))?
+                    .as_str();
+                if id.is_empty() {
+                    return None;
+                }
+                id.to_string()
+            }
+        };
+        let info = self.devices.get(&id).cloned().unwrap_or_default();
+        Some(Device { id, info })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"
+pattern = "^[^_]*_(?P<device>[^_]+)"
+quantities = ["temperature", "humidity"]
+
+[aliases]
+"mqtt.0.adfhome.Temperatur_Keller_alt" = "Keller"
+
+[devices]
+Keller = { name = "Basement sensor", room = "Basement" }
+"#;
+
+    #[test]
+    fn resolve_takes_the_device_group_of_the_pattern() {
+        let map = DeviceMap::bundled().unwrap();
+        let device = map.resolve("mqtt.0.adfhome.Temperatur_Wohnzimmer").unwrap();
+        assert_eq!(device.id, "Wohnzimmer");
+        assert!(device.info.name.is_none());
+        assert_eq!(map.resolve("adfhome/Temperatur_Bad_oben").unwrap().id, "Bad");
+        assert!(map.resolve("mqtt.0.adfhome.Temperatur").is_none());
+    }
+
+    #[test]
+    fn resolve_checks_aliases_first_and_adds_the_device_info() {
+        let map = DeviceMap::from_toml(MAP).unwrap();
+        let device = map.resolve("mqtt.0.adfhome.Temperatur_Keller_alt").unwrap();
+        assert_eq!(device.id, "Keller");
+        assert_eq!(device.info.name.as_deref(), Some("Basement sensor"));
+        assert_eq!(device.info.room.as_deref(), Some("Basement"));
+        assert!(device.info.floor.is_none());
+        assert_eq!(map.resolve("mqtt.0.adfhome.Temperatur_Keller").unwrap().info.room.as_deref(), Some("Basement"));
+    }
+
+    #[test]
+    fn resolve_falls_back_to_the_first_group_or_the_whole_match() {
+        let map = DeviceMap::from_toml(r#"pattern = "sensors/([a-z]+)/state""#).unwrap();
+        assert_eq!(map.resolve("home/sensors/kitchen/state").unwrap().id, "kitchen");
+        let map = DeviceMap::from_toml(r#"pattern = "[A-Z][a-z]+$""#).unwrap();
+        assert_eq!(map.resolve("mqtt.0.Kitchen").unwrap().id, "Kitchen");
+        let map = DeviceMap::from_toml(r#"pattern = "sensor_([a-z]*)""#).unwrap();
+        assert!(map.resolve("sensor_").is_none());
+    }
+
+    #[test]
+    fn accepts_only_the_listed_quantities() {
+        let map = DeviceMap::from_toml(MAP).unwrap();
+        assert!(map.accepts("humidity"));
+        assert!(!map.accepts("linkquality"));
+        let map = DeviceMap::from_toml(r#"pattern = ".+""#).unwrap();
+        assert!(map.accepts("linkquality"));
+    }
+
+    #[test]
+    fn from_toml_rejects_invalid_patterns() {
+        assert!(matches!(DeviceMap::from_toml(r#"pattern = "(""#), Err(DeviceMapError::InvalidPattern(_))));
+    }
+}
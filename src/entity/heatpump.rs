@@ -7,63 +7,75 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub event_timestamp: DateTimeWithTimeZone,
-    pub ambient_state: String,
-    #[sea_orm(column_type = "Double")]
-    pub ambient_temperaturecalculated: f64,
-    #[sea_orm(column_type = "Double")]
-    pub boiler_hightemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub boiler_lowtemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub boiler_maxtemp: f64,
-    pub boiler_state: String,
-    #[sea_orm(column_type = "Double")]
-    pub buffer_hightemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub buffer_lowtemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub buffer_maxtemp: f64,
-    pub buffer_state: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatingcircuit_1_flowtemp: f64,
-    pub heatingcircuit_1_state: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatingcircuit_2_flowtemp: f64,
-    pub heatingcircuit_2_state: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_actualheatingcapacity: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_compressorrating: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_currentcop: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_electricenergy: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_energysourceinlettemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_errornumber: f64,
-    pub heatpump_errorstate: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_flowlinetemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_heatenergy: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_inverteractualpower: f64,
-    pub heatpump_operatingstate: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_requestflowtemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_requestreturntemp: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_requesttempdiff: f64,
-    pub heatpump_requesttype: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_returnlinetemp: f64,
-    pub heatpump_state: String,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_volumesink: f64,
-    #[sea_orm(column_type = "Double")]
-    pub heatpump_volumesourceflow: f64,
+    pub clock_skew_ms: i64,
+    pub ambient_errornumber: Option<i32>,
+    pub ambient_state: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub ambient_temperature1h: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub ambient_temperature24h: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub ambient_temperaturecalculated: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub boiler_hightemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub boiler_lowtemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub boiler_maxtemp: Option<f64>,
+    pub boiler_state: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub buffer_hightemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub buffer_lowtemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub buffer_maxtemp: Option<f64>,
+    pub buffer_state: Option<String>,
+    pub emanager_errornumber: Option<i32>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatingcircuit_1_flowtemp: Option<f64>,
+    pub heatingcircuit_1_state: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatingcircuit_2_flowtemp: Option<f64>,
+    pub heatingcircuit_2_state: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_actualheatingcapacity: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_compressorrating: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_currentcop: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_electricenergy: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_energysourceinlettemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_errornumber: Option<f64>,
+    pub heatpump_errorstate: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_flowlinetemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_heatenergy: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_inverteractualpower: Option<f64>,
+    pub heatpump_operatingstate: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_requestflowtemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_requestreturntemp: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_requesttempdiff: Option<f64>,
+    pub heatpump_requesttype: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_returnlinetemp: Option<f64>,
+    pub heatpump_state: Option<String>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_volumesink: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub heatpump_volumesourceflow: Option<f64>,
+    #[sea_orm(column_type = "JsonBinary")]
+    pub suspect_fields: Json,
+    #[sea_orm(column_type = "JsonBinary")]
+    pub conversion_errors: Json,
+    pub inserted_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
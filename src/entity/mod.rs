@@ -1,6 +1,15 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.10
 
+#[allow(unused_imports)]
 pub mod prelude;
 
+pub mod boiler;
+pub mod buffer;
+pub mod charging_session;
+pub mod heating_circuit;
 pub mod heatpump;
-pub mod temperature_data;
+pub mod heatpump_unit;
+pub mod pv_data;
+pub mod sensor_device;
+pub mod sensor_reading;
+pub mod solar_module;
@@ -1,4 +1,12 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.10
 
+pub use super::boiler::Entity as Boiler;
+pub use super::buffer::Entity as Buffer;
+pub use super::charging_session::Entity as ChargingSession;
+pub use super::heating_circuit::Entity as HeatingCircuit;
 pub use super::heatpump::Entity as Heatpump;
-pub use super::temperature_data::Entity as TemperatureData;
+pub use super::heatpump_unit::Entity as HeatpumpUnit;
+pub use super::pv_data::Entity as PvData;
+pub use super::sensor_device::Entity as SensorDevice;
+pub use super::sensor_reading::Entity as SensorReading;
+pub use super::solar_module::Entity as SolarModule;
@@ -2,13 +2,19 @@
 
 use sea_orm::entity::prelude::*;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
-#[sea_orm(table_name = "temperature_data")]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "pv_data")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub event_timestamp: DateTimeWithTimeZone,
-    #[sea_orm(column_type = "JsonBinary")]
-    pub data: Json,
+    pub clock_skew_ms: i64,
+    pub battery_power: i64,
+    #[sea_orm(column_type = "Double")]
+    pub battery_percentage: f64,
+    pub grid: i64,
+    pub home: i64,
+    pub pv: i64,
+    pub wallbox: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -3,25 +3,102 @@ mod mapper;
 mod client;
 mod postgres_client;
 mod entity;
+mod modbus_client;
+mod mqtt_client;
+mod subscription_client;
+mod retry;
+mod backfill;
+mod register_map;
+mod device_map;
+mod charging_session;
+mod migrator;
+mod spool;
+mod timescale;
+mod retention;
+mod rollup;
 
 use std::env;
-use crate::client::IoBrokerClient;
-use crate::mapper::{map_lamda_data, map_to_temperature};
+use std::path::PathBuf;
+use crate::client::{IoBrokerAuth, IoBrokerClient, IoBrokerClientOptions, IoBrokerTls};
+use crate::retry::{CircuitBreakerConfig, RetryPolicy};
+use crate::modbus_client::LambdaModbusClient;
+use crate::mqtt_client::MqttTemperatureClient;
+use crate::subscription_client::{IoBrokerSubscriptionClient, StateCache};
+use crate::models::model_iobroker::IoBrokerResponse;
+use crate::mapper::{map_lamda_data, map_pv_data, map_to_sensor_readings, MappingMode, PvStates, QualityAction, QualityRules, TimestampStrategy, ValueScaling};
+use crate::register_map::{ModuleKind, RegisterMap};
+use crate::device_map::DeviceMap;
 use std::error::Error;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use tokio::time::{self, Duration};
 use tokio::signal::ctrl_c;
 use tokio::sync::oneshot;
 use crate::postgres_client::PostgresClient;
+use crate::spool::Spool;
+use crate::retention::{RetentionJob, RetentionPolicy};
+use crate::rollup::RollupJob;
+use crate::backfill::run_backfill;
+use crate::charging_session::{ChargingSessionDetector, ChargingSessionRules};
+
+const TEMPERATURE_PATTERN: &str = "mqtt.0.adfhome.Temperatur*";
+
+// How ioBroker states are obtained, selected with IOBROKER_MODE
+#[derive(Clone)]
+enum IoBrokerStates {
+    Polling(IoBrokerClient),
+    Subscription(StateCache),
+}
+
+impl IoBrokerStates {
+    async fn fetch_states(&self, pattern: &str) -> Result<IoBrokerResponse, Box<dyn Error>> {
+        match self {
+            IoBrokerStates::Polling(client) => Ok(client.fetch_data(format!("/states?filter={}", pattern)).await?),
+            IoBrokerStates::Subscription(cache) => Ok(cache.snapshot_matching(pattern)),
+        }
+    }
+
+    async fn fetch_ids(&self, ids: &[&str]) -> Result<IoBrokerResponse, Box<dyn Error>> {
+        match self {
+            IoBrokerStates::Polling(client) => Ok(client.fetch_states(ids).await?),
+            IoBrokerStates::Subscription(cache) => Ok(cache.snapshot_ids(ids)),
+        }
+    }
+}
+
+// Where the heat pump registers are read from, selected with LAMBDA_SOURCE
+enum LambdaSource {
+    IoBroker(IoBrokerStates, RegisterMap),
+    // Reads the registers of the map directly, so values arrive unscaled
+    Modbus(LambdaModbusClient, RegisterMap),
+}
+
+impl LambdaSource {
+    async fn fetch_data(&self) -> Result<IoBrokerResponse, Box<dyn Error>> {
+        match self {
+            LambdaSource::IoBroker(states, register_map) => states.fetch_ids(&register_map.state_ids()).await,
+            LambdaSource::Modbus(client, register_map) => Ok(client.fetch_data(register_map).await?),
+        }
+    }
+
+    fn scaling(&self) -> ValueScaling {
+        match self {
+            LambdaSource::IoBroker(..) => ValueScaling::PreScaled,
+            LambdaSource::Modbus(..) => ValueScaling::Raw,
+        }
+    }
+
+    fn register_map(&self) -> &RegisterMap {
+        match self {
+            LambdaSource::IoBroker(_, register_map) | LambdaSource::Modbus(_, register_map) => register_map,
+        }
+    }
+}
 
 // Example handler functions
-async fn handle_short_interval(io_broker:&IoBrokerClient,database_client:&PostgresClient) -> Result<(), Box<dyn Error>> {
-    let lambda_data = match io_broker.fetch_data("/states?filter=modbus.0.holdingRegisters.*".to_string()).await {
-        Ok(data) => data,
-        Err(e) => Err(e)?
-    };
+async fn handle_short_interval(lambda_source:&LambdaSource,quality_rules:&QualityRules,mapping_mode:MappingMode,timestamps:TimestampStrategy,database_client:&PostgresClient) -> Result<(), Box<dyn Error>> {
+    let lambda_data = lambda_source.fetch_data().await?;
 
-    let mapped_lambda_data = match map_lamda_data(&lambda_data) {
+    let mapped_lambda_data = match map_lamda_data(&lambda_data, lambda_source.register_map(), quality_rules, mapping_mode, lambda_source.scaling(), timestamps) {
         Ok(mapped_data) => mapped_data,
         Err(e) => {
             eprintln!("Error mapping data: {}", e);
@@ -30,15 +107,15 @@ async fn handle_short_interval(io_broker:&IoBrokerClient,database_client:&Postgr
     };
     print!("Lambda data: {:#?} \n\n", &mapped_lambda_data );
     // Save the mapped data to the database     
-    print!("Saving data to database...\n");
+    println!("Saving data to database...");
     database_client.write_lambda_data(mapped_lambda_data.clone()).await?;
     println!("Lambda data saved: {} \n {} \n\n", Utc::now().naive_local() , &mapped_lambda_data);
     Ok(())
 }
 
-async fn handle_long_interval(io_broker:&IoBrokerClient,database_client:&PostgresClient) -> Result<(), Box<dyn Error>> {
+async fn handle_long_interval(io_broker:&IoBrokerStates,device_map:&DeviceMap,timestamps:TimestampStrategy,database_client:&PostgresClient) -> Result<(), Box<dyn Error>> {
 
-    let temperature_data = match io_broker.fetch_data("/states?filter=mqtt.0.adfhome.Temperatur*".to_string()).await {
+    let temperature_data = match io_broker.fetch_states(TEMPERATURE_PATTERN).await {
         Ok(data) => data,
         Err(e) => {
             eprintln!("Error fetching temperature: {}", e);
@@ -46,19 +123,60 @@ async fn handle_long_interval(io_broker:&IoBrokerClient,database_client:&Postgre
         }
     };
 
-    let mapped_temperature_data = match map_to_temperature(temperature_data) {
+    let mapped_temperature_data = match map_to_sensor_readings(temperature_data, device_map, timestamps) {
         Ok(mapped_data) => mapped_data,
         Err(e) => {
             eprintln!("Error mapping data: {}", e);
             Err(e)?
         }
     };
+    if !mapped_temperature_data.unmatched_devices.is_empty() {
+        eprintln!("No device found for temperature states: {}", mapped_temperature_data.unmatched_devices.join(", "));
+    }
 
     database_client.write_temperature_data(mapped_temperature_data.clone()).await?;
     println!("Temperature data saved: {} \n {:#?} \n\n", Utc::now().naive_local(), &mapped_temperature_data );
     Ok(())
 } 
 
+async fn handle_pv_interval(io_broker:&IoBrokerStates,pv_states:&PvStates,session_detector:&mut ChargingSessionDetector,timestamps:TimestampStrategy,database_client:&PostgresClient) -> Result<(), Box<dyn Error>> {
+    let pv_data = io_broker.fetch_ids(&pv_states.ids()).await?;
+
+    let mapped_pv_data = match map_pv_data(&pv_data, pv_states, timestamps) {
+        Ok(mapped_data) => mapped_data,
+        Err(e) => {
+            eprintln!("Error mapping PV data: {}", e);
+            Err(e)?
+        }
+    };
+    database_client.write_pv_data(mapped_pv_data.clone()).await?;
+    println!("PV data saved: {} \n {} \n\n", Utc::now().naive_local(), &mapped_pv_data);
+    if let Some(session) = session_detector.push(mapped_pv_data) {
+        database_client.write_charging_session(session.clone()).await?;
+        println!("Charging session saved: \n {} \n\n", &session);
+    }
+    Ok(())
+}
+
+// Parses an optional environment variable, falling back to the default when it is not set
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value.parse().map_err(|e| format!("{} environment variable error: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
+// Resolves when a subscribed trigger state changed, never when polling
+async fn state_change_trigger(io_broker:&IoBrokerStates) {
+    match io_broker {
+        IoBrokerStates::Subscription(cache) => cache.triggered().await,
+        IoBrokerStates::Polling(_) => std::future::pending().await,
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
    
@@ -69,24 +187,262 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let postgres_user = env::var("POSTGRES_USER").map_err(|e| format!("POSTGRES_USER error: {}", e))?;
     let postgres_password = env::var("POSTGRES_PASSWORD").map_err(|e| format!("POSTGRES_PASSWORD environment variable error: {}", e))?;
     let postgres_database = env::var("POSTGRES_DATABASE").map_err(|e| format!("POSTGRES_DATABASE environment variable error: {}", e))?;
+    let spool_path = PathBuf::from(env::var("SPOOL_PATH").unwrap_or_else(|_| "spool.jsonl".to_string()));
+    let spool = Spool::open(&spool_path, env_or::<u64>("SPOOL_MAX_MB", 100)? * 1024 * 1024)?;
     let database_client = PostgresClient::new(
         postgres_user,
         postgres_password,
         postgres_host,
         postgres_database,
         postgres_port,
-    ).await?;
-    let io_broker_client = IoBrokerClient::new(broker_url.to_string())?;
+    ).await?.with_spool(spool);
+    let io_broker_options = IoBrokerClientOptions {
+        auth: match (env::var("IOBROKER_USER"), env::var("IOBROKER_PASSWORD"), env::var("IOBROKER_TOKEN")) {
+            (_, _, Ok(token)) => IoBrokerAuth::Bearer(token),
+            (Ok(user), Ok(password), _) => IoBrokerAuth::Basic { user, password },
+            _ => IoBrokerAuth::None,
+        },
+        tls: IoBrokerTls {
+            ca_bundle: env::var("IOBROKER_CA_BUNDLE").ok().map(PathBuf::from),
+            client_certificate: env::var("IOBROKER_CLIENT_CERT").ok().map(PathBuf::from),
+            client_key: env::var("IOBROKER_CLIENT_KEY").ok().map(PathBuf::from),
+            pinned_certificate: env::var("IOBROKER_PINNED_CERT").ok().map(PathBuf::from),
+        },
+        retry: RetryPolicy {
+            max_retries: env_or("IOBROKER_MAX_RETRIES", RetryPolicy::default().max_retries)?,
+            initial_backoff: Duration::from_millis(env_or("IOBROKER_BACKOFF_MS", 500)?),
+            max_backoff: Duration::from_millis(env_or("IOBROKER_MAX_BACKOFF_MS", 10_000)?),
+            ..RetryPolicy::default()
+        },
+        circuit_breaker: CircuitBreakerConfig {
+            failure_threshold: env_or("IOBROKER_BREAKER_THRESHOLD", CircuitBreakerConfig::default().failure_threshold)?,
+            open_duration: Duration::from_secs(env_or("IOBROKER_BREAKER_OPEN_SECS", 60)?),
+        },
+    };
+    let defaults = QualityRules::default();
+    let quality_rules = QualityRules {
+        check_quality: env::var("QUALITY_CHECK_Q").map(|v| v == "true").unwrap_or(defaults.check_quality),
+        require_ack: env::var("QUALITY_REQUIRE_ACK").map(|v| v == "true").unwrap_or(defaults.require_ack),
+        max_age: match env::var("QUALITY_MAX_AGE_SECS") {
+            Ok(secs) => Some(chrono::Duration::seconds(secs.parse().map_err(|e| format!("QUALITY_MAX_AGE_SECS environment variable error: {}", e))?)),
+            Err(_) => defaults.max_age,
+        },
+        action: match env::var("QUALITY_ACTION").unwrap_or_else(|_| "flag".to_string()).as_str() {
+            "flag" => QualityAction::Flag,
+            "reject" => QualityAction::Reject,
+            other => Err(format!("QUALITY_ACTION environment variable error: unknown action {}", other))?,
+        },
+    };
+    let mapping_mode = match env::var("LAMBDA_MAPPING_MODE").unwrap_or_else(|_| "strict".to_string()).as_str() {
+        "strict" => MappingMode::Strict,
+        "tolerant" => MappingMode::Tolerant,
+        other => Err(format!("LAMBDA_MAPPING_MODE environment variable error: unknown mode {}", other))?,
+    };
+    let register_map = match env::var("LAMBDA_REGISTER_MAP") {
+        Ok(path) => RegisterMap::load(&PathBuf::from(path))?,
+        Err(_) => RegisterMap::bundled()?,
+    };
+    let device_map = match env::var("TEMPERATURE_DEVICE_MAP") {
+        Ok(path) => DeviceMap::load(&PathBuf::from(path))?,
+        Err(_) => DeviceMap::bundled()?,
+    };
+    let pv_states = match env::var("PV_ENABLED").map(|v| v == "true").unwrap_or(false) {
+        true => {
+            let mut states = PvStates::with_prefix(&env::var("PV_STATE_PREFIX").unwrap_or_else(|_| PvStates::DEFAULT_PREFIX.to_string()));
+            // `field=state` pairs for adapters that name the states differently
+            for entry in env::var("PV_STATES").unwrap_or_default().split(',').filter(|entry| !entry.trim().is_empty()) {
+                let (field, state) = entry.split_once('=').ok_or_else(|| format!("PV_STATES environment variable error: expected field=state, got {}", entry))?;
+                states.set(field.trim(), state.trim().to_string()).map_err(|e| format!("PV_STATES environment variable error: {}", e))?;
+            }
+            Some(states)
+        }
+        false => None,
+    };
+    let charging_defaults = ChargingSessionRules::default();
+    let charging_rules = ChargingSessionRules {
+        min_power: env_or("CHARGING_MIN_POWER_W", charging_defaults.min_power)?,
+        idle_timeout: chrono::Duration::seconds(env_or("CHARGING_IDLE_SECS", charging_defaults.idle_timeout.num_seconds())?),
+        max_gap: chrono::Duration::seconds(env_or("CHARGING_MAX_GAP_SECS", charging_defaults.max_gap.num_seconds())?),
+    };
+    let io_broker_client = IoBrokerClient::new(broker_url.to_string(), &io_broker_options)?;
+    let args: Vec<String> = env::args().collect();
+    let timescale = match env::var("DATABASE_TIMESCALE").unwrap_or_else(|_| "off".to_string()).as_str() {
+        "on" => true,
+        "off" => false,
+        other => Err(format!("DATABASE_TIMESCALE environment variable error: unknown mode {}", other))?,
+    };
+    // `fetcherRS migrate [up|down|status] [steps]` changes the schema and exits
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        let steps = match args.get(3) {
+            Some(steps) => Some(steps.parse::<u32>().map_err(|e| format!("migrate steps {} error: {}", steps, e))?),
+            None => None,
+        };
+        match args.get(2).map(String::as_str).unwrap_or("up") {
+            "up" => {
+                database_client.migrate_up(steps, timescale).await?;
+            }
+            // Rolling back everything by accident drops all samples, so one step is the default
+            "down" => database_client.migrate_down(Some(steps.unwrap_or(1))).await?,
+            "status" => {}
+            other => Err(format!("unknown migrate command {}, expected up, down or status", other))?,
+        }
+        for (name, status) in database_client.migration_status().await? {
+            println!("{:<8} {}", status, name);
+        }
+        return Ok(());
+    }
+    let timescale_active = match env::var("DATABASE_MIGRATE").unwrap_or_else(|_| "auto".to_string()).as_str() {
+        "auto" => database_client.migrate_on_startup(timescale).await?,
+        "off" => timescale,
+        other => Err(format!("DATABASE_MIGRATE environment variable error: unknown mode {}", other))?,
+    };
+    // `fetcherRS charging-summary` prints the charging sessions per month and exits
+    if args.get(1).map(String::as_str) == Some("charging-summary") {
+        println!("{:<8} {:>8} {:>10} {:>10} {:>10} {:>10} {:>8}", "Month", "Sessions", "kWh", "PV kWh", "Bat. kWh", "Grid kWh", "PV %");
+        for month in database_client.charging_months().await? {
+            println!(
+                "{:<8} {:>8} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>8}",
+                month.month.format("%Y-%m"),
+                month.sessions,
+                month.energy_kwh,
+                month.pv_energy_kwh,
+                month.battery_energy_kwh,
+                month.grid_energy_kwh,
+                month.pv_share.map(|share| format!("{:.0}", share * 100.0)).unwrap_or_else(|| "n/a".to_string()),
+            );
+        }
+        return Ok(());
+    }
+    // `fetcherRS backfill [from] [to]` fills gaps from the ioBroker history adapter and exits
+    if args.get(1).map(String::as_str) == Some("backfill") {
+        let to = match args.get(3) {
+            Some(to) => DateTime::parse_from_rfc3339(to).map_err(|e| format!("backfill end {} error: {}", to, e))?.to_utc(),
+            None => Utc::now(),
+        };
+        let from = match args.get(2) {
+            Some(from) => DateTime::parse_from_rfc3339(from).map_err(|e| format!("backfill start {} error: {}", from, e))?.to_utc(),
+            None => to - chrono::Duration::days(7),
+        };
+        run_backfill(&io_broker_client, &database_client, &register_map, &device_map, &quality_rules, TEMPERATURE_PATTERN, from, to).await?;
+        println!("Backfill finished");
+        return Ok(());
+    }
+    let io_broker_states = match env::var("IOBROKER_MODE").unwrap_or_else(|_| "poll".to_string()).as_str() {
+        "poll" => IoBrokerStates::Polling(io_broker_client),
+        "subscribe" => {
+            let socket_url = env::var("IOBROKER_SOCKET_URL").map_err(|e| format!("IOBROKER_SOCKET_URL environment variable error: {}", e))?;
+            // The HP1 state and operating state of the register map, so a changed prefix is followed
+            let default_triggers = ["state", "operating_state"]
+                .map(|field| register_map.instance_state(ModuleKind::HeatPump, 0, field))
+                .join(",");
+            let trigger_ids = env::var("IOBROKER_TRIGGER_STATES")
+                .unwrap_or(default_triggers)
+                .split(',')
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect();
+            let mut patterns = register_map.subscription_patterns();
+            patterns.push("mqtt.0.*".to_string());
+            if let Some(pv_states) = &pv_states {
+                patterns.extend(pv_states.ids().iter().map(|id| id.to_string()));
+            }
+            let subscription_client = IoBrokerSubscriptionClient::new(
+                socket_url,
+                io_broker_options.clone(),
+                patterns,
+                trigger_ids,
+            );
+            let cache = subscription_client.cache();
+            tokio::spawn(subscription_client.run());
+            IoBrokerStates::Subscription(cache)
+        }
+        other => Err(format!("IOBROKER_MODE environment variable error: unknown mode {}", other))?,
+    };
+    let lambda_source = match env::var("LAMBDA_SOURCE").unwrap_or_else(|_| "iobroker".to_string()).as_str() {
+        "iobroker" => LambdaSource::IoBroker(io_broker_states.clone(), register_map),
+        "modbus" => {
+            let modbus_address = env::var("LAMBDA_MODBUS_ADDRESS").map_err(|e| format!("LAMBDA_MODBUS_ADDRESS environment variable error: {}", e))?;
+            let modbus_unit_id:u8 = env::var("LAMBDA_MODBUS_UNIT_ID").unwrap_or_else(|_| "1".to_string()).parse().map_err(|e| format!("LAMBDA_MODBUS_UNIT_ID environment variable error: {}", e))?;
+            LambdaSource::Modbus(LambdaModbusClient::new(modbus_address, modbus_unit_id), register_map)
+        }
+        other => Err(format!("LAMBDA_SOURCE environment variable error: unknown source {}", other))?,
+    };
+    if quality_rules.check_quality && matches!(lambda_source, LambdaSource::IoBroker(IoBrokerStates::Polling(_), _)) {
+        eprintln!("Warning: the simple-api getBulk endpoint does not report q, QUALITY_CHECK_Q only applies to heat pump states in subscribe mode");
+    }
+    let timestamp_strategy = match env::var("TIMESTAMP_STRATEGY").unwrap_or_else(|_| "newest".to_string()).as_str() {
+        "newest" => TimestampStrategy::Newest,
+        "median" => TimestampStrategy::Median,
+        "local" => TimestampStrategy::Local,
+        other => Err(format!("TIMESTAMP_STRATEGY environment variable error: unknown strategy {}", other))?,
+    };
+    let poll_temperatures = match env::var("TEMPERATURE_SOURCE").unwrap_or_else(|_| "iobroker".to_string()).as_str() {
+        "iobroker" => true,
+        "mqtt" => {
+            let mqtt_host = env::var("MQTT_HOST").map_err(|e| format!("MQTT_HOST environment variable error: {}", e))?;
+            let mqtt_port:u16 = env::var("MQTT_PORT").unwrap_or_else(|_| "1883".to_string()).parse().map_err(|e| format!("MQTT_PORT environment variable error: {}", e))?;
+            let mqtt_client_id = env::var("MQTT_CLIENT_ID").unwrap_or_else(|_| "fetcher".to_string());
+            let mqtt_credentials = match (env::var("MQTT_USER"), env::var("MQTT_PASSWORD")) {
+                (Ok(user), Ok(password)) => Some((user, password)),
+                _ => None,
+            };
+            let mqtt_topics = env::var("MQTT_TOPICS").unwrap_or_else(|_| "adfhome/Temperatur_+".to_string())
+                .split(',')
+                .map(|topic| topic.trim().to_string())
+                .collect();
+            let mqtt_client = MqttTemperatureClient::new(mqtt_host, mqtt_port, mqtt_client_id, mqtt_credentials, mqtt_topics, device_map.clone());
+            tokio::spawn(mqtt_client.run(database_client.clone()));
+            false
+        }
+        other => Err(format!("TEMPERATURE_SOURCE environment variable error: unknown source {}", other))?,
+    };
+
+    // TimescaleDB keeps its own continuous aggregates
+    let run_rollups = match env::var("ROLLUP_JOB").unwrap_or_else(|_| "auto".to_string()).as_str() {
+        "auto" => !timescale_active,
+        "on" => true,
+        "off" => false,
+        other => Err(format!("ROLLUP_JOB environment variable error: unknown mode {}", other))?,
+    };
+    if run_rollups {
+        let rollup_job = RollupJob::new(Duration::from_secs(env_or("ROLLUP_INTERVAL_SECS", 300)?));
+        tokio::spawn(rollup_job.run(database_client.clone()));
+    }
+    // `table=days` pairs, tables without an entry are kept forever
+    let mut retention_policies = Vec::new();
+    for entry in env::var("RETENTION").unwrap_or_default().split(',').filter(|entry| !entry.trim().is_empty()) {
+        let (table, days) = entry.split_once('=').ok_or_else(|| format!("RETENTION environment variable error: expected table=days, got {}", entry))?;
+        let days = days.trim().parse().map_err(|e| format!("RETENTION environment variable error: {} days {}: {}", table, days, e))?;
+        retention_policies.push(RetentionPolicy::new(table.trim(), days).map_err(|e| format!("RETENTION environment variable error: {}", e))?);
+    }
+    // Only rows the rollup job rolled up are deleted, without it the retention would never delete anything
+    if !retention_policies.is_empty() && !run_rollups {
+        Err("RETENTION environment variable error: retention needs the rollup job, set ROLLUP_JOB=on")?;
+    }
+    let retention_batch_size: u64 = env_or("RETENTION_BATCH_SIZE", 5000)?;
+    if retention_batch_size == 0 {
+        Err("RETENTION_BATCH_SIZE environment variable error: at least one row has to be deleted per batch")?;
+    }
+    if !retention_policies.is_empty() {
+        let retention_job = RetentionJob::new(
+            retention_policies,
+            retention_batch_size,
+            Duration::from_secs(env_or("RETENTION_INTERVAL_SECS", 3600)?),
+        );
+        tokio::spawn(retention_job.run(database_client.clone()));
+    }
 
     let mut short_interval = time::interval(Duration::from_secs(30));
     let mut long_interval = time::interval(Duration::from_secs(60 * 15));
+    let mut pv_interval = time::interval(Duration::from_secs(env_or("PV_INTERVAL_SECS", 60)?));
+    let mut session_detector = ChargingSessionDetector::new(charging_rules);
 
     // Create a shutdown channel
     let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
 
     // Spawn a task to handle Ctrl+C
     tokio::spawn(async move {
-        if let Ok(_) = ctrl_c().await {
+        if ctrl_c().await.is_ok() {
             let _ = shutdown_tx.send(());
         }
     });
@@ -95,18 +451,34 @@ async fn main() -> Result<(), Box<dyn Error>> {
         tokio::select! {
             _ = short_interval.tick() => {
                 println!("30-Seconds interval triggered");
-                if let Err(e) = handle_short_interval(&io_broker_client, &database_client).await {
+                if let Err(e) = handle_short_interval(&lambda_source, &quality_rules, mapping_mode, timestamp_strategy, &database_client).await {
                     eprintln!("Error l: {}", e);
                 }
             }
-             _ = long_interval.tick() => {
+             _ = long_interval.tick(), if poll_temperatures => {
                 println!("30-Minutes interval triggered");
-                if let Err(e) = handle_long_interval(&io_broker_client, &database_client).await {
+                if let Err(e) = handle_long_interval(&io_broker_states, &device_map, timestamp_strategy, &database_client).await {
                     eprintln!("Error in long interval: {}", e);
                 }
             } 
+            _ = pv_interval.tick(), if pv_states.is_some() => {
+                if let Some(pv_states) = &pv_states
+                    && let Err(e) = handle_pv_interval(&io_broker_states, pv_states, &mut session_detector, timestamp_strategy, &database_client).await {
+                    eprintln!("Error in PV interval: {}", e);
+                }
+            }
+            _ = state_change_trigger(&io_broker_states) => {
+                println!("Heat pump state changed, sampling immediately");
+                if let Err(e) = handle_short_interval(&lambda_source, &quality_rules, mapping_mode, timestamp_strategy, &database_client).await {
+                    eprintln!("Error l: {}", e);
+                }
+            }
             _ = &mut shutdown_rx => {
                 println!("Shutdown signal received, cleaning up...");
+                if let Some(session) = session_detector.finish()
+                    && let Err(e) = database_client.write_charging_session(session).await {
+                    eprintln!("Error saving open charging session: {}", e);
+                }
                 break;
             }
         }
@@ -1,16 +1,23 @@
 use crate::models::{
-    model_iobroker::IoBrokerResponse, model_lambda::LambdaData, model_temperature::TemperatureData,
+    model_charging::ChargingSession,
+    model_iobroker::{IoBrokerResponse, IoBrokerValue},
+    model_lambda::{BoilerData, BufferData, HeatPumpData, HeatingCircuitData, LambdaData, LambdaEnum, SolarData},
+    model_pv::PvData,
+    model_sensor::{SensorReading, SensorSample},
 };
 
+use crate::device_map::DeviceMap;
+use crate::register_map::{ModuleKind, RegisterMap};
 use crate::entity::{
-    heatpump::ActiveModel as HeatPumpModel, temperature_data::ActiveModel as TemperatureModel,
+    boiler, buffer, charging_session::ActiveModel as ChargingSessionModel, heating_circuit, heatpump::ActiveModel as HeatPumpModel, heatpump_unit,
+    pv_data::ActiveModel as PvDataModel, sensor_device, sensor_reading, solar_module,
 };
-use chrono::Utc;
-use sea_orm::Set;
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{NotSet, Set};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io::Error;
-use strum::IntoEnumIterator;
 
 impl std::error::Error for ConversionError {}
 
@@ -18,6 +25,8 @@ impl std::error::Error for ConversionError {}
 pub enum ConversionError {
     InvalidData(String),
     KeyNotFound(String),
+    RejectedValue(String, String),
+    UnknownDevice(String),
 }
 
 impl fmt::Display for ConversionError {
@@ -25,284 +34,884 @@ impl fmt::Display for ConversionError {
         match self {
             ConversionError::InvalidData(msg) => write!(f, "Invalid data: {}", msg),
             ConversionError::KeyNotFound(key) => write!(f, "Key not found: {}", key),
+            ConversionError::RejectedValue(key, reason) => write!(f, "Rejected value {}: {}", key, reason),
+            ConversionError::UnknownDevice(source) => write!(f, "No device found for {}", source),
         }
     }
 }
 
-fn get_enum<T: IntoEnumIterator>(
-    broker_value: &IoBrokerResponse,
-    key: String,
-) -> Result<T, ConversionError> {
-    let result = match get_value(broker_value, key.clone()) {
-        Ok(val) => T::iter()
-            .nth(val)
-            .ok_or(ConversionError::InvalidData(key.clone())),
-        Err(e) => Err(e),
-    };
-    result
+/// What happens to a state that breaks one of the [`QualityRules`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QualityAction {
+    /// Fail the whole sample
+    Reject,
+    /// Store the value but record the state in `suspect_fields`
+    Flag,
+}
+
+/// Checks applied to the ioBroker `q`, `ack` and `ts` fields of every mapped state
+#[derive(Debug, Clone)]
+pub struct QualityRules {
+    pub check_quality: bool,
+    pub require_ack: bool,
+    pub max_age: Option<Duration>,
+    pub action: QualityAction,
+}
+
+impl Default for QualityRules {
+    fn default() -> Self {
+        Self {
+            check_quality: true,
+            require_ack: false,
+            max_age: None,
+            action: QualityAction::Flag,
+        }
+    }
+}
+
+impl QualityRules {
+    fn issues(&self, value: &IoBrokerValue, now: i64) -> Vec<String> {
+        let mut issues = Vec::new();
+        // Sources that do not report q or ack are not checked for them
+        if let Some(q) = value.q.filter(|q| self.check_quality && *q != 0) {
+            issues.push(format!("bad quality 0x{:02x}", q));
+        }
+        if self.require_ack && value.ack == Some(false) {
+            issues.push(String::from("not acknowledged"));
+        }
+        if let Some(max_age) = self.max_age {
+            let age = Duration::milliseconds(now - value.ts);
+            if age > max_age {
+                issues.push(format!("stale, {}s old", age.num_seconds()));
+            }
+        }
+        issues
+    }
+}
+
+/// What happens when a mapped state is missing or cannot be parsed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MappingMode {
+    /// Fail the whole sample
+    Strict,
+    /// Leave the field empty and record the error in `conversion_errors`
+    Tolerant,
+}
+
+/// Whether state values still have to be scaled with the register map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueScaling {
+    /// Values are already in their unit, as the ioBroker modbus adapter applies the factors
+    PreScaled,
+    /// Values are raw register values, as read over Modbus
+    Raw,
+}
+
+/// How the stored event timestamp is derived from the ioBroker `ts` of the mapped states
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampStrategy {
+    /// Newest `ts` of all mapped states
+    Newest,
+    /// Median `ts` of all mapped states, robust against single states that were updated late
+    Median,
+    /// Local time when the sample was mapped
+    Local,
+}
+
+impl TimestampStrategy {
+    /// Event timestamp for a sample together with the skew of the local clock to it in milliseconds
+    pub fn event_timestamp(&self, mut timestamps: Vec<i64>) -> (DateTime<Utc>, i64) {
+        let now = Utc::now();
+        let source = match self {
+            TimestampStrategy::Newest => timestamps.iter().max().copied(),
+            TimestampStrategy::Median => {
+                timestamps.sort_unstable();
+                timestamps.get(timestamps.len() / 2).copied()
+            }
+            TimestampStrategy::Local => None,
+        };
+
+        match source.and_then(DateTime::from_timestamp_millis) {
+            Some(timestamp) => (timestamp, (now - timestamp).num_milliseconds()),
+            None => (now, 0),
+        }
+    }
+}
+
+// Reads states out of an ioBroker response and applies the quality rules to each of them
+struct StateReader<'a> {
+    broker_value: &'a IoBrokerResponse,
+    register_map: &'a RegisterMap,
+    rules: &'a QualityRules,
+    mode: MappingMode,
+    scaling: ValueScaling,
+    now: i64,
+    suspect_fields: BTreeMap<String, String>,
+    conversion_errors: BTreeMap<String, String>,
+    fields_read: usize,
+    timestamps: Vec<i64>,
+}
+
+impl<'a> StateReader<'a> {
+    fn new(
+        broker_value: &'a IoBrokerResponse,
+        register_map: &'a RegisterMap,
+        rules: &'a QualityRules,
+        mode: MappingMode,
+        scaling: ValueScaling,
+    ) -> Self {
+        Self {
+            broker_value,
+            register_map,
+            rules,
+            mode,
+            scaling,
+            now: Utc::now().timestamp_millis(),
+            suspect_fields: BTreeMap::new(),
+            conversion_errors: BTreeMap::new(),
+            fields_read: 0,
+            timestamps: Vec::new(),
+        }
+    }
+
+    fn get_enum<T: LambdaEnum>(&mut self, field: &str) -> Result<Option<T>, ConversionError> {
+        let register_map = self.register_map;
+        self.read_enum(register_map.state(field))
+    }
+
+    fn get_value<T: std::str::FromStr>(&mut self, field: &str) -> Result<Option<T>, ConversionError> {
+        let register_map = self.register_map;
+        self.read_value(register_map.state(field))
+    }
+
+    fn get_module_enum<T: LambdaEnum>(
+        &mut self,
+        kind: ModuleKind,
+        instance: usize,
+        field: &str,
+    ) -> Result<Option<T>, ConversionError> {
+        let register_map = self.register_map;
+        self.read_enum(register_map.instance_state(kind, instance, field))
+    }
+
+    fn get_module_value<T: std::str::FromStr>(
+        &mut self,
+        kind: ModuleKind,
+        instance: usize,
+        field: &str,
+    ) -> Result<Option<T>, ConversionError> {
+        let register_map = self.register_map;
+        self.read_value(register_map.instance_state(kind, instance, field))
+    }
+
+    fn get_module_flag(
+        &mut self,
+        kind: ModuleKind,
+        instance: usize,
+        field: &str,
+    ) -> Result<Option<bool>, ConversionError> {
+        let register_map = self.register_map;
+        self.read_flag(register_map.instance_state(kind, instance, field))
+    }
+
+    // Instances of a module with at least one state in the response, modules that are not
+    // installed have no states at all
+    fn present_instances(&self, kind: ModuleKind) -> Vec<usize> {
+        (0..self.register_map.instances(kind))
+            .filter(|instance| {
+                self.register_map
+                    .instance_states(kind, *instance)
+                    .any(|key| self.broker_value.contains_key(key))
+            })
+            .collect()
+    }
+
+    fn read_enum<T: LambdaEnum>(&mut self, key: &str) -> Result<Option<T>, ConversionError> {
+        let result = self.parse_state(key).map(T::from_code);
+        self.tolerate(key, result)
+    }
+
+    fn read_value<T: std::str::FromStr>(&mut self, key: &str) -> Result<Option<T>, ConversionError> {
+        let result = self.parse_state(key);
+        self.tolerate(key, result)
+    }
+
+    // Relay and pump registers hold 0 or 1, ioBroker may also report them as booleans
+    fn read_flag(&mut self, key: &str) -> Result<Option<bool>, ConversionError> {
+        let result = self.parse_state::<String>(key).and_then(|value| match value.as_str() {
+            "1" | "true" => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(ConversionError::InvalidData(key.to_string())),
+        });
+        self.tolerate(key, result)
+    }
+
+    // Quality rejections always fail the sample, missing and unparsable states only in strict mode.
+    // Optional states the adapter does not have are skipped in every mode.
+    fn tolerate<T>(&mut self, key: &str, result: Result<T, ConversionError>) -> Result<Option<T>, ConversionError> {
+        if matches!(result, Err(ConversionError::KeyNotFound(_))) && self.register_map.is_optional(key) {
+            return Ok(None);
+        }
+        self.fields_read += 1;
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e @ ConversionError::RejectedValue(..)) => Err(e),
+            Err(e) if self.mode == MappingMode::Tolerant => {
+                self.conversion_errors.insert(key.to_string(), e.to_string());
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn parse_state<T: std::str::FromStr>(&mut self, key: &str) -> Result<T, ConversionError> {
+        // First, try to get the IoBrokerValue for the given key
+        let value = self
+            .broker_value
+            .get(key)
+            .ok_or_else(|| ConversionError::KeyNotFound(key.to_string()))?;
+
+        self.timestamps.push(value.ts);
+        let issues = self.rules.issues(value, self.now);
+        if !issues.is_empty() {
+            match self.rules.action {
+                QualityAction::Reject => {
+                    return Err(ConversionError::RejectedValue(key.to_string(), issues.join(", ")));
+                }
+                QualityAction::Flag => {
+                    self.suspect_fields.insert(key.to_string(), issues.join(", "));
+                }
+            }
+        }
+
+        let val = match (self.scaling, self.register_map.description(key)) {
+            (ValueScaling::Raw, Some(description)) => {
+                let raw = value
+                    .val
+                    .parse::<i64>()
+                    .map_err(|_| ConversionError::InvalidData(key.to_string()))?;
+                description.scaled(raw)
+            }
+            _ => value.val.clone(),
+        };
+
+        // Then try to parse the val field into type T
+        val.parse::<T>()
+            .map_err(|_| ConversionError::InvalidData(key.to_string()))
+    }
 }
 
-fn get_value<T: std::str::FromStr>(
+pub fn map_lamda_data(
     broker_value: &IoBrokerResponse,
-    key: String,
-) -> Result<T, ConversionError> {
-    // First, try to get the IoBrokerValue for the given key
-    let value = broker_value
-        .get(&key)
-        .ok_or_else(|| ConversionError::KeyNotFound(key.clone()))?;
-
-    // Then try to parse the val field into type T
-    value
-        .val
-        .parse::<T>()
-        .map_err(|_| ConversionError::InvalidData(key.clone()))
-}
-
-pub fn map_lamda_data(broker_value: &IoBrokerResponse) -> Result<LambdaData, ConversionError> {
-    let model = LambdaData {
-        ambient_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.40002_Ambient_State"),
-        )?,
-        ambient_temperature_calculated: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.40005_Ambient_Calculated_Temp"),
-        )?,
-        emanager_operating_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.40102_E_Manager_State"),
-        )?,
-        emanager_actual_power: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.40104_E_Manager_Actual"),
-        )?,
-        emanager_pv_power: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.40103_E-Manager_ExcessPower"),
-        )?,
-        emanager_power_setpoint: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.40105_E_Manager_Setpoint"),
-        )?,
-        heatpump_error_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41001_HP1_Error_State"),
-        )?,
-        heatpump_error_number: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41002_HP1_Error"),
-        )?,
-        heatpump_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41003_HP1_State"),
-        )?,
-        heatpump_operating_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41004_HP1_OperatingState"),
-        )?,
-        heatpump_flowline_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41005_HP1_T_Flow"),
-        )?,
-        heatpump_return_line_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41006_HP1_T_Return"),
-        )?,
-        heatpump_volume_sink: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41007_HP1_Vol_Sink"),
-        )?,
-        heatpump_energy_source_inlet_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41008_HP1_T_EQin"),
-        )?,
-        heatpump_volume_source_flow: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41010_HP1_Vol_Source"),
-        )?,
-        heatpump_compressor_rating: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41011_HP1_CompressorRating"),
-        )?,
-        heatpump_actual_heating_capacity: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41012_HP1_QpHeating"),
-        )?,
-        heatpump_inverter_actual_power: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41013_HP1_FI_PowerConsumption"),
-        )?,
-        heatpump_current_cop: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41014_HP1_COP"),
-        )?,
-        heatpump_request_type: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41016_HP1_RequestType"),
-        )?,
-        heatpump_request_flow_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41017_HP1_RequestT_Flow"),
-        )?,
-        heatpump_request_return_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41018_HP1_RequestT_Return"),
-        )?,
-        heatpump_request_temp_diff: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41019_HP1_RequestT_Diff"),
-        )?,
-        heatpump_electric_energy: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41021_HP1_VdA_E"),
-        )?,
-        heatpump_heat_energy: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.41023_HP1_VdA_Q"),
-        )?,
-        boiler_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.42002_Boiler1_OperatingState"),
-        )?,
-        boiler_high_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.42003_Boiler1_ActualHighTemp"),
-        )?,
-        boiler_low_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.42004_Boiler1_ActualLowTemp"),
-        )?,
-        boiler_max_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.42051_Boiler1_MaximumTemp"),
-        )?,
-        buffer_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.43002_Buffer1_OperatingState"),
-        )?,
-        buffer_high_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.43003_Buffer1_ActualHighTemp"),
-        )?,
-        buffer_low_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.43004_Buffer1_ActualLowTemp"),
-        )?,
-        buffer_max_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.43051_Buffer1_MaximumTemp"),
-        )?,
-        heating_circuit_1_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.45002_Heating1_OperatingState"),
-        )?,
-        heating_circuit_2_state: get_enum(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.45102_Heating2_OperatingState"),
-        )?,
-        heating_circuit_1_flow_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.45003_Heating1_T_Flow"),
-        )?,
-        heating_circuit_2_flow_temp: get_value(
-            broker_value,
-            String::from("modbus.0.holdingRegisters.45103_Heating2_T_Flow"),
-        )?,
+    register_map: &RegisterMap,
+    rules: &QualityRules,
+    mode: MappingMode,
+    scaling: ValueScaling,
+    timestamps: TimestampStrategy,
+) -> Result<LambdaData, ConversionError> {
+    let mut reader = StateReader::new(broker_value, register_map, rules, mode, scaling);
+    let mut model = LambdaData {
+        event_timestamp: Utc::now(),
+        clock_skew_ms: 0,
+        ambient_error_number: reader.get_value("ambient_error_number")?,
+        ambient_state: reader.get_enum("ambient_state")?,
+        ambient_temperature_1h: reader.get_value("ambient_temperature_1h")?,
+        ambient_temperature_24h: reader.get_value("ambient_temperature_24h")?,
+        ambient_temperature_calculated: reader.get_value("ambient_temperature_calculated")?,
+        emanager_error_number: reader.get_value("emanager_error_number")?,
+        emanager_operating_state: reader.get_enum("emanager_operating_state")?,
+        emanager_actual_power: reader.get_value("emanager_actual_power")?,
+        emanager_pv_power: reader.get_value("emanager_pv_power")?,
+        emanager_power_setpoint: reader.get_value("emanager_power_setpoint")?,
+        heat_pumps: Vec::new(),
+        boilers: Vec::new(),
+        buffers: Vec::new(),
+        solar_modules: Vec::new(),
+        heating_circuits: Vec::new(),
+        units: register_map.units(),
+        suspect_fields: BTreeMap::new(),
+        conversion_errors: BTreeMap::new(),
     };
+    for instance in reader.present_instances(ModuleKind::HeatPump) {
+        model.heat_pumps.push(map_heat_pump(&mut reader, instance)?);
+    }
+    for instance in reader.present_instances(ModuleKind::Boiler) {
+        model.boilers.push(map_boiler(&mut reader, instance)?);
+    }
+    for instance in reader.present_instances(ModuleKind::Buffer) {
+        model.buffers.push(map_buffer(&mut reader, instance)?);
+    }
+    for instance in reader.present_instances(ModuleKind::Solar) {
+        model.solar_modules.push(map_solar(&mut reader, instance)?);
+    }
+    for instance in reader.present_instances(ModuleKind::HeatingCircuit) {
+        model.heating_circuits.push(map_heating_circuit(&mut reader, instance)?);
+    }
+
+    if reader.conversion_errors.len() == reader.fields_read {
+        return Err(ConversionError::InvalidData(String::from("no heat pump state could be read")));
+    }
+    model.suspect_fields = reader.suspect_fields;
+    model.conversion_errors = reader.conversion_errors;
+    (model.event_timestamp, model.clock_skew_ms) = timestamps.event_timestamp(reader.timestamps);
     Ok(model)
 }
 
-pub fn map_to_temperature(response: IoBrokerResponse) -> Result<Vec<TemperatureData>, Error> {
-    let temperature_data: Vec<TemperatureData> = response
-        .into_iter()
-        .filter(|(_, value)| !value.val.is_empty())
-        .filter_map(|(device, value)| {
-            let json_result: Value = match serde_json::from_str(&value.val) {
-                Ok(result) => result,
-                Err(_) => return None,
-            };
+fn map_heat_pump(reader: &mut StateReader, instance: usize) -> Result<HeatPumpData, ConversionError> {
+    let kind = ModuleKind::HeatPump;
+    Ok(HeatPumpData {
+        index: instance as u8 + 1,
+        error_state: reader.get_module_enum(kind, instance, "error_state")?,
+        error_number: reader.get_module_value(kind, instance, "error_number")?,
+        state: reader.get_module_enum(kind, instance, "state")?,
+        operating_state: reader.get_module_enum(kind, instance, "operating_state")?,
+        flowline_temp: reader.get_module_value(kind, instance, "flowline_temp")?,
+        return_line_temp: reader.get_module_value(kind, instance, "return_line_temp")?,
+        volume_sink: reader.get_module_value(kind, instance, "volume_sink")?,
+        energy_source_inlet_temp: reader.get_module_value(kind, instance, "energy_source_inlet_temp")?,
+        energy_source_outlet_temp: reader.get_module_value(kind, instance, "energy_source_outlet_temp")?,
+        volume_source_flow: reader.get_module_value(kind, instance, "volume_source_flow")?,
+        compressor_rating: reader.get_module_value(kind, instance, "compressor_rating")?,
+        actual_heating_capacity: reader.get_module_value(kind, instance, "actual_heating_capacity")?,
+        inverter_actual_power: reader.get_module_value(kind, instance, "inverter_actual_power")?,
+        current_cop: reader.get_module_value(kind, instance, "current_cop")?,
+        request_type: reader.get_module_enum(kind, instance, "request_type")?,
+        request_flow_temp: reader.get_module_value(kind, instance, "request_flow_temp")?,
+        request_return_temp: reader.get_module_value(kind, instance, "request_return_temp")?,
+        request_temp_diff: reader.get_module_value(kind, instance, "request_temp_diff")?,
+        second_stage_active: reader.get_module_flag(kind, instance, "second_stage_active")?,
+        electric_energy: reader.get_module_value(kind, instance, "electric_energy")?,
+        heat_energy: reader.get_module_value(kind, instance, "heat_energy")?,
+    })
+}
 
-            if !json_result["temperature"].is_null() {
-                let temperature = match json_result["temperature"].to_string().parse::<f64>() {
-                    Ok(temp) => temp,
-                    Err(_) => return None,
-                };
+fn map_boiler(reader: &mut StateReader, instance: usize) -> Result<BoilerData, ConversionError> {
+    let kind = ModuleKind::Boiler;
+    Ok(BoilerData {
+        index: instance as u8 + 1,
+        error_number: reader.get_module_value(kind, instance, "error_number")?,
+        state: reader.get_module_enum(kind, instance, "state")?,
+        high_temp: reader.get_module_value(kind, instance, "high_temp")?,
+        low_temp: reader.get_module_value(kind, instance, "low_temp")?,
+        circulation_temp: reader.get_module_value(kind, instance, "circulation_temp")?,
+        circulation_pump_active: reader.get_module_flag(kind, instance, "circulation_pump_active")?,
+        max_temp: reader.get_module_value(kind, instance, "max_temp")?,
+    })
+}
 
-                let formatted_device = device.split("_").collect::<Vec<&str>>()[1].to_string();
+fn map_buffer(reader: &mut StateReader, instance: usize) -> Result<BufferData, ConversionError> {
+    let kind = ModuleKind::Buffer;
+    Ok(BufferData {
+        index: instance as u8 + 1,
+        error_number: reader.get_module_value(kind, instance, "error_number")?,
+        state: reader.get_module_enum(kind, instance, "state")?,
+        high_temp: reader.get_module_value(kind, instance, "high_temp")?,
+        low_temp: reader.get_module_value(kind, instance, "low_temp")?,
+        request_type: reader.get_module_enum(kind, instance, "request_type")?,
+        request_flow_temp: reader.get_module_value(kind, instance, "request_flow_temp")?,
+        request_return_temp: reader.get_module_value(kind, instance, "request_return_temp")?,
+        request_temp_diff: reader.get_module_value(kind, instance, "request_temp_diff")?,
+        request_capacity: reader.get_module_value(kind, instance, "request_capacity")?,
+        max_temp: reader.get_module_value(kind, instance, "max_temp")?,
+    })
+}
 
-                Some(TemperatureData {
-                    device: formatted_device,
-                    value: temperature,
-                })
-            } else {
-                None
+fn map_solar(reader: &mut StateReader, instance: usize) -> Result<SolarData, ConversionError> {
+    let kind = ModuleKind::Solar;
+    Ok(SolarData {
+        index: instance as u8 + 1,
+        error_number: reader.get_module_value(kind, instance, "error_number")?,
+        state: reader.get_module_enum(kind, instance, "state")?,
+        collector_temp: reader.get_module_value(kind, instance, "collector_temp")?,
+        buffer_1_temp: reader.get_module_value(kind, instance, "buffer_1_temp")?,
+        buffer_2_temp: reader.get_module_value(kind, instance, "buffer_2_temp")?,
+        max_buffer_temp: reader.get_module_value(kind, instance, "max_buffer_temp")?,
+        buffer_changeover_temp: reader.get_module_value(kind, instance, "buffer_changeover_temp")?,
+    })
+}
+
+fn map_heating_circuit(reader: &mut StateReader, instance: usize) -> Result<HeatingCircuitData, ConversionError> {
+    let kind = ModuleKind::HeatingCircuit;
+    Ok(HeatingCircuitData {
+        index: instance as u8 + 1,
+        error_number: reader.get_module_value(kind, instance, "error_number")?,
+        state: reader.get_module_enum(kind, instance, "state")?,
+        flow_temp: reader.get_module_value(kind, instance, "flow_temp")?,
+        return_temp: reader.get_module_value(kind, instance, "return_temp")?,
+        room_temp: reader.get_module_value(kind, instance, "room_temp")?,
+        flow_setpoint: reader.get_module_value(kind, instance, "flow_setpoint")?,
+        operating_mode: reader.get_module_enum(kind, instance, "operating_mode")?,
+        flow_offset: reader.get_module_value(kind, instance, "flow_offset")?,
+        room_heating_setpoint: reader.get_module_value(kind, instance, "room_heating_setpoint")?,
+        room_cooling_setpoint: reader.get_module_value(kind, instance, "room_cooling_setpoint")?,
+    })
+}
+
+pub fn map_to_sensor_readings(
+    response: IoBrokerResponse,
+    device_map: &DeviceMap,
+    timestamps: TimestampStrategy,
+) -> Result<SensorSample, Error> {
+    let mut readings = Vec::new();
+    let mut source_timestamps = Vec::new();
+    let mut unmatched_devices = Vec::new();
+    for (state, value) in response.into_iter().filter(|(_, value)| !value.val.is_empty()) {
+        match map_sensor_payload(device_map, &state, &value.val) {
+            Ok(Some(reading)) => {
+                readings.push(reading);
+                source_timestamps.push(value.ts);
             }
-        })
+            Ok(None) => {}
+            // Reported by the caller, the other sensors are still stored
+            Err(_) => unmatched_devices.push(state),
+        }
+    }
+
+    let (event_timestamp, clock_skew_ms) = timestamps.event_timestamp(source_timestamps);
+    Ok(SensorSample {
+        event_timestamp,
+        clock_skew_ms,
+        readings,
+        unmatched_devices,
+    })
+}
+
+/// Maps a single sensor payload, either an ioBroker state value or an MQTT message,
+/// where `source` is the state ID or topic the payload was published on.
+/// Payloads without an accepted quantity give `None`, readings of unknown devices an error.
+pub fn map_sensor_payload(
+    device_map: &DeviceMap,
+    source: &str,
+    payload: &str,
+) -> Result<Option<SensorReading>, ConversionError> {
+    let json_result: Value = match serde_json::from_str(payload) {
+        Ok(result) => result,
+        Err(_) => return Ok(None),
+    };
+    let Some(fields) = json_result.as_object() else {
+        return Ok(None);
+    };
+
+    let quantities: BTreeMap<String, f64> = fields
+        .iter()
+        .filter(|(quantity, _)| device_map.accepts(quantity))
+        .filter_map(|(quantity, value)| value.as_f64().map(|value| (quantity.clone(), value)))
         .collect();
+    if quantities.is_empty() {
+        return Ok(None);
+    }
 
-    Ok(temperature_data)
+    let device = device_map
+        .resolve(source)
+        .ok_or_else(|| ConversionError::UnknownDevice(source.to_string()))?;
+    Ok(Some(SensorReading {
+        device: device.id,
+        quantities,
+        name: device.info.name,
+        room: device.info.room,
+        floor: device.info.floor,
+    }))
+}
+
+/// ioBroker state IDs the inverter values are read from, defaults to the E3DC adapter
+#[derive(Debug, Clone)]
+pub struct PvStates {
+    pub battery_power: String,
+    pub battery_percentage: String,
+    pub grid: String,
+    pub home: String,
+    pub pv: String,
+    pub wallbox: String,
+}
+
+impl PvStates {
+    pub const DEFAULT_PREFIX: &'static str = "e3dc-rscp.0.EMS.";
+
+    /// States of the E3DC adapter instance below `prefix`
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            battery_power: format!("{}POWER_BAT", prefix),
+            battery_percentage: format!("{}BAT_SOC", prefix),
+            grid: format!("{}POWER_GRID", prefix),
+            home: format!("{}POWER_HOME", prefix),
+            pv: format!("{}POWER_PV", prefix),
+            wallbox: format!("{}POWER_WB_ALL", prefix),
+        }
+    }
+
+    /// Replaces the state of a single field, for inverters whose adapter names them differently
+    pub fn set(&mut self, field: &str, state: String) -> Result<(), String> {
+        let target = match field {
+            "battery_power" => &mut self.battery_power,
+            "battery_percentage" => &mut self.battery_percentage,
+            "grid" => &mut self.grid,
+            "home" => &mut self.home,
+            "pv" => &mut self.pv,
+            "wallbox" => &mut self.wallbox,
+            other => return Err(format!("unknown PV field {}", other)),
+        };
+        *target = state;
+        Ok(())
+    }
+
+    pub fn ids(&self) -> [&str; 6] {
+        [&self.battery_power, &self.battery_percentage, &self.grid, &self.home, &self.pv, &self.wallbox]
+    }
+}
+
+pub fn map_pv_data(
+    response: &IoBrokerResponse,
+    states: &PvStates,
+    timestamps: TimestampStrategy,
+) -> Result<PvData, ConversionError> {
+    let mut source_timestamps = Vec::new();
+    let mut read = |key: &str| -> Result<f64, ConversionError> {
+        let value = response
+            .get(key)
+            .ok_or_else(|| ConversionError::KeyNotFound(key.to_string()))?;
+        source_timestamps.push(value.ts);
+        value
+            .val
+            .parse::<f64>()
+            .map_err(|_| ConversionError::InvalidData(key.to_string()))
+    };
+    // Adapters report the power values as floats in W, whole watts are stored
+    let battery_power = read(&states.battery_power)?.round() as i64;
+    let battery_percentage = read(&states.battery_percentage)?;
+    let grid = read(&states.grid)?.round() as i64;
+    let home = read(&states.home)?.round() as i64;
+    let pv = read(&states.pv)?.round() as i64;
+    let wallbox = read(&states.wallbox)?.round() as i64;
+
+    let (event_timestamp, clock_skew_ms) = timestamps.event_timestamp(source_timestamps);
+    Ok(PvData {
+        event_timestamp,
+        clock_skew_ms,
+        battery_power,
+        battery_percentage,
+        grid,
+        home,
+        pv,
+        wallbox,
+    })
 }
 
 pub trait ToLambdaDataModel {
     fn to_lambda_data(self) -> HeatPumpModel;
 }
 
-pub trait ToTemperatureDataModel {
-    fn to_temperature_data(self) -> TemperatureModel;
+pub trait ToPvDataModel {
+    fn to_pv_data(self) -> PvDataModel;
 }
 
-impl ToTemperatureDataModel for Vec<TemperatureData> {
-    fn to_temperature_data(self) -> TemperatureModel {
-        let json_value = serde_json::to_value(&self).unwrap_or_default();
-
-        TemperatureModel {
+impl ToPvDataModel for PvData {
+    fn to_pv_data(self) -> PvDataModel {
+        PvDataModel {
             event_timestamp: Set(
-                Utc::now().with_timezone(&chrono::FixedOffset::east_opt(0).unwrap())
+                self.event_timestamp.with_timezone(&chrono::FixedOffset::east_opt(0).unwrap())
             ),
-            data: Set(json_value),
+            clock_skew_ms: Set(self.clock_skew_ms),
+            battery_power: Set(self.battery_power),
+            battery_percentage: Set(self.battery_percentage),
+            grid: Set(self.grid),
+            home: Set(self.home),
+            pv: Set(self.pv),
+            wallbox: Set(self.wallbox),
+        }
+    }
+}
+
+pub trait ToChargingSessionModel {
+    fn to_charging_session(self) -> ChargingSessionModel;
+}
+
+impl ToChargingSessionModel for ChargingSession {
+    fn to_charging_session(self) -> ChargingSessionModel {
+        let utc = chrono::FixedOffset::east_opt(0).unwrap();
+        ChargingSessionModel {
+            started_at: Set(self.started_at.with_timezone(&utc)),
+            ended_at: Set(self.ended_at.with_timezone(&utc)),
+            energy_wh: Set(self.energy_wh),
+            pv_energy_wh: Set(self.pv_energy_wh),
+            battery_energy_wh: Set(self.battery_energy_wh),
+            grid_energy_wh: Set(self.grid_energy_wh),
+            peak_power: Set(self.peak_power),
         }
     }
 }
 
+pub trait ToSensorReadingModels {
+    fn to_sensor_devices(&self) -> Vec<sensor_device::ActiveModel>;
+    fn to_sensor_readings(&self) -> Vec<sensor_reading::ActiveModel>;
+}
+
+impl ToSensorReadingModels for SensorSample {
+    // One row per device, a device can report through several states
+    fn to_sensor_devices(&self) -> Vec<sensor_device::ActiveModel> {
+        let devices: BTreeMap<&str, &SensorReading> = self
+            .readings
+            .iter()
+            .map(|reading| (reading.device.as_str(), reading))
+            .collect();
+        devices
+            .into_values()
+            .map(|reading| sensor_device::ActiveModel {
+                id: Set(reading.device.clone()),
+                name: Set(reading.name.clone()),
+                room: Set(reading.room.clone()),
+                floor: Set(reading.floor.clone()),
+            })
+            .collect()
+    }
+
+    fn to_sensor_readings(&self) -> Vec<sensor_reading::ActiveModel> {
+        let event_timestamp = module_timestamp(self.event_timestamp);
+        self.readings
+            .iter()
+            .flat_map(|reading| {
+                reading.quantities.iter().map(move |(quantity, value)| sensor_reading::ActiveModel {
+                    event_timestamp: Set(event_timestamp),
+                    device_id: Set(reading.device.clone()),
+                    quantity: Set(quantity.clone()),
+                    value: Set(*value),
+                    clock_skew_ms: Set(self.clock_skew_ms),
+                    inserted_at: NotSet,
+                })
+            })
+            .collect()
+    }
+}
+
 impl ToLambdaDataModel for LambdaData {
+    // The wide table only has columns for the first heat pump, boiler and buffer and the
+    // first two heating circuits, all instances are stored through ToModuleModel
     fn to_lambda_data(self) -> HeatPumpModel {
+        let heat_pump = self.heat_pumps.iter().find(|module| module.index == 1);
+        let boiler = self.boilers.iter().find(|module| module.index == 1);
+        let buffer = self.buffers.iter().find(|module| module.index == 1);
+        let heating_circuit_1 = self.heating_circuits.iter().find(|module| module.index == 1);
+        let heating_circuit_2 = self.heating_circuits.iter().find(|module| module.index == 2);
+
         HeatPumpModel {
             event_timestamp: Set(
-                Utc::now().with_timezone(&chrono::FixedOffset::east_opt(0).unwrap())
+                self.event_timestamp.with_timezone(&chrono::FixedOffset::east_opt(0).unwrap())
             ),
-            ambient_state: Set(self.ambient_state.to_string()),
+            clock_skew_ms: Set(self.clock_skew_ms),
+            ambient_errornumber: Set(self.ambient_error_number),
+            ambient_state: Set(self.ambient_state.map(|state| state.to_string())),
+            ambient_temperature1h: Set(self.ambient_temperature_1h),
+            ambient_temperature24h: Set(self.ambient_temperature_24h),
             ambient_temperaturecalculated: Set(self.ambient_temperature_calculated),
-            boiler_hightemp: Set(self.boiler_high_temp),
-            boiler_lowtemp: Set(self.boiler_low_temp),
-            boiler_maxtemp: Set(self.boiler_max_temp),
-            boiler_state: Set(self.boiler_state.to_string()),
-            buffer_hightemp: Set(self.buffer_high_temp),
-            buffer_lowtemp: Set(self.buffer_low_temp),
-            buffer_maxtemp: Set(self.buffer_max_temp),
-            buffer_state: Set(self.buffer_state.to_string()),
-            heatingcircuit_1_flowtemp: Set(self.heating_circuit_1_flow_temp),
-            heatingcircuit_1_state: Set(self.heating_circuit_1_state.to_string()),
-            heatingcircuit_2_flowtemp: Set(self.heating_circuit_2_flow_temp),
-            heatingcircuit_2_state: Set(self.heating_circuit_2_state.to_string()),
-            heatpump_actualheatingcapacity: Set(self.heatpump_actual_heating_capacity),
-            heatpump_compressorrating: Set(self.heatpump_compressor_rating),
-            heatpump_currentcop: Set(self.heatpump_current_cop),
-            heatpump_electricenergy: Set(self.heatpump_electric_energy),
-            heatpump_energysourceinlettemp: Set(self.heatpump_energy_source_inlet_temp),
-            heatpump_errornumber: Set(self.heatpump_error_number as f64),
-            heatpump_errorstate: Set(self.heatpump_error_state.to_string()),
-            heatpump_flowlinetemp: Set(self.heatpump_flowline_temp),
-            heatpump_heatenergy: Set(self.heatpump_heat_energy),
-            heatpump_inverteractualpower: Set(self.heatpump_inverter_actual_power),
-            heatpump_operatingstate: Set(self.heatpump_operating_state.to_string()),
-            heatpump_requestflowtemp: Set(self.heatpump_request_flow_temp),
-            heatpump_requestreturntemp: Set(self.heatpump_request_return_temp),
-            heatpump_requesttempdiff: Set(self.heatpump_request_temp_diff),
-            heatpump_requesttype: Set(self.heatpump_request_type.to_string()),
-            heatpump_returnlinetemp: Set(self.heatpump_return_line_temp),
-            heatpump_state: Set(self.heatpump_state.to_string()),
-            heatpump_volumesink: Set(self.heatpump_volume_sink),
-            heatpump_volumesourceflow: Set(self.heatpump_volume_source_flow),
+            boiler_hightemp: Set(boiler.and_then(|module| module.high_temp)),
+            boiler_lowtemp: Set(boiler.and_then(|module| module.low_temp)),
+            boiler_maxtemp: Set(boiler.and_then(|module| module.max_temp)),
+            boiler_state: Set(boiler.and_then(|module| module.state).map(|state| state.to_string())),
+            buffer_hightemp: Set(buffer.and_then(|module| module.high_temp)),
+            buffer_lowtemp: Set(buffer.and_then(|module| module.low_temp)),
+            buffer_maxtemp: Set(buffer.and_then(|module| module.max_temp)),
+            buffer_state: Set(buffer.and_then(|module| module.state).map(|state| state.to_string())),
+            emanager_errornumber: Set(self.emanager_error_number),
+            heatingcircuit_1_flowtemp: Set(heating_circuit_1.and_then(|module| module.flow_temp)),
+            heatingcircuit_1_state: Set(heating_circuit_1.and_then(|module| module.state).map(|state| state.to_string())),
+            heatingcircuit_2_flowtemp: Set(heating_circuit_2.and_then(|module| module.flow_temp)),
+            heatingcircuit_2_state: Set(heating_circuit_2.and_then(|module| module.state).map(|state| state.to_string())),
+            heatpump_actualheatingcapacity: Set(heat_pump.and_then(|module| module.actual_heating_capacity)),
+            heatpump_compressorrating: Set(heat_pump.and_then(|module| module.compressor_rating)),
+            heatpump_currentcop: Set(heat_pump.and_then(|module| module.current_cop)),
+            heatpump_electricenergy: Set(heat_pump.and_then(|module| module.electric_energy)),
+            heatpump_energysourceinlettemp: Set(heat_pump.and_then(|module| module.energy_source_inlet_temp)),
+            heatpump_errornumber: Set(heat_pump.and_then(|module| module.error_number).map(f64::from)),
+            heatpump_errorstate: Set(heat_pump.and_then(|module| module.error_state).map(|state| state.to_string())),
+            heatpump_flowlinetemp: Set(heat_pump.and_then(|module| module.flowline_temp)),
+            heatpump_heatenergy: Set(heat_pump.and_then(|module| module.heat_energy)),
+            heatpump_inverteractualpower: Set(heat_pump.and_then(|module| module.inverter_actual_power)),
+            heatpump_operatingstate: Set(heat_pump.and_then(|module| module.operating_state).map(|state| state.to_string())),
+            heatpump_requestflowtemp: Set(heat_pump.and_then(|module| module.request_flow_temp)),
+            heatpump_requestreturntemp: Set(heat_pump.and_then(|module| module.request_return_temp)),
+            heatpump_requesttempdiff: Set(heat_pump.and_then(|module| module.request_temp_diff)),
+            heatpump_requesttype: Set(heat_pump.and_then(|module| module.request_type).map(|state| state.to_string())),
+            heatpump_returnlinetemp: Set(heat_pump.and_then(|module| module.return_line_temp)),
+            heatpump_state: Set(heat_pump.and_then(|module| module.state).map(|state| state.to_string())),
+            heatpump_volumesink: Set(heat_pump.and_then(|module| module.volume_sink)),
+            heatpump_volumesourceflow: Set(heat_pump.and_then(|module| module.volume_source_flow)),
+            suspect_fields: Set(serde_json::to_value(&self.suspect_fields).unwrap_or_default()),
+            conversion_errors: Set(serde_json::to_value(&self.conversion_errors).unwrap_or_default()),
+            // Set by the database, the rollup job finds late rows by it
+            inserted_at: NotSet,
+        }
+    }
+}
+
+/// Row of a cascaded module instance in its own table
+pub trait ToModuleModel {
+    type Model;
+    fn to_module_model(&self, event_timestamp: DateTime<Utc>) -> Self::Model;
+}
+
+fn module_timestamp(event_timestamp: DateTime<Utc>) -> DateTime<chrono::FixedOffset> {
+    event_timestamp.with_timezone(&chrono::FixedOffset::east_opt(0).unwrap())
+}
+
+impl ToModuleModel for HeatPumpData {
+    type Model = heatpump_unit::ActiveModel;
+
+    fn to_module_model(&self, event_timestamp: DateTime<Utc>) -> Self::Model {
+        heatpump_unit::ActiveModel {
+            event_timestamp: Set(module_timestamp(event_timestamp)),
+            module_index: Set(self.index as i16),
+            error_state: Set(self.error_state.map(|state| state.to_string())),
+            error_number: Set(self.error_number.map(f64::from)),
+            state: Set(self.state.map(|state| state.to_string())),
+            operating_state: Set(self.operating_state.map(|state| state.to_string())),
+            flowline_temp: Set(self.flowline_temp),
+            return_line_temp: Set(self.return_line_temp),
+            volume_sink: Set(self.volume_sink),
+            energy_source_inlet_temp: Set(self.energy_source_inlet_temp),
+            energy_source_outlet_temp: Set(self.energy_source_outlet_temp),
+            volume_source_flow: Set(self.volume_source_flow),
+            compressor_rating: Set(self.compressor_rating),
+            actual_heating_capacity: Set(self.actual_heating_capacity),
+            inverter_actual_power: Set(self.inverter_actual_power),
+            current_cop: Set(self.current_cop),
+            request_type: Set(self.request_type.map(|state| state.to_string())),
+            request_flow_temp: Set(self.request_flow_temp),
+            request_return_temp: Set(self.request_return_temp),
+            request_temp_diff: Set(self.request_temp_diff),
+            second_stage_active: Set(self.second_stage_active),
+            electric_energy: Set(self.electric_energy),
+            heat_energy: Set(self.heat_energy),
+        }
+    }
+}
+
+impl ToModuleModel for BoilerData {
+    type Model = boiler::ActiveModel;
+
+    fn to_module_model(&self, event_timestamp: DateTime<Utc>) -> Self::Model {
+        boiler::ActiveModel {
+            event_timestamp: Set(module_timestamp(event_timestamp)),
+            module_index: Set(self.index as i16),
+            error_number: Set(self.error_number),
+            state: Set(self.state.map(|state| state.to_string())),
+            high_temp: Set(self.high_temp),
+            low_temp: Set(self.low_temp),
+            circulation_temp: Set(self.circulation_temp),
+            circulation_pump_active: Set(self.circulation_pump_active),
+            max_temp: Set(self.max_temp),
+        }
+    }
+}
+
+impl ToModuleModel for BufferData {
+    type Model = buffer::ActiveModel;
+
+    fn to_module_model(&self, event_timestamp: DateTime<Utc>) -> Self::Model {
+        buffer::ActiveModel {
+            event_timestamp: Set(module_timestamp(event_timestamp)),
+            module_index: Set(self.index as i16),
+            error_number: Set(self.error_number),
+            state: Set(self.state.map(|state| state.to_string())),
+            high_temp: Set(self.high_temp),
+            low_temp: Set(self.low_temp),
+            request_type: Set(self.request_type.map(|state| state.to_string())),
+            request_flow_temp: Set(self.request_flow_temp),
+            request_return_temp: Set(self.request_return_temp),
+            request_temp_diff: Set(self.request_temp_diff),
+            request_capacity: Set(self.request_capacity),
+            max_temp: Set(self.max_temp),
         }
     }
 }
+
+impl ToModuleModel for SolarData {
+    type Model = solar_module::ActiveModel;
+
+    fn to_module_model(&self, event_timestamp: DateTime<Utc>) -> Self::Model {
+        solar_module::ActiveModel {
+            event_timestamp: Set(module_timestamp(event_timestamp)),
+            module_index: Set(self.index as i16),
+            error_number: Set(self.error_number),
+            state: Set(self.state.map(|state| state.to_string())),
+            collector_temp: Set(self.collector_temp),
+            buffer_1_temp: Set(self.buffer_1_temp),
+            buffer_2_temp: Set(self.buffer_2_temp),
+            max_buffer_temp: Set(self.max_buffer_temp),
+            buffer_changeover_temp: Set(self.buffer_changeover_temp),
+        }
+    }
+}
+
+impl ToModuleModel for HeatingCircuitData {
+    type Model = heating_circuit::ActiveModel;
+
+    fn to_module_model(&self, event_timestamp: DateTime<Utc>) -> Self::Model {
+        heating_circuit::ActiveModel {
+            event_timestamp: Set(module_timestamp(event_timestamp)),
+            module_index: Set(self.index as i16),
+            error_number: Set(self.error_number),
+            state: Set(self.state.map(|state| state.to_string())),
+            flow_temp: Set(self.flow_temp),
+            return_temp: Set(self.return_temp),
+            room_temp: Set(self.room_temp),
+            flow_setpoint: Set(self.flow_setpoint),
+            operating_mode: Set(self.operating_mode.map(|mode| mode.to_string())),
+            flow_offset: Set(self.flow_offset),
+            room_heating_setpoint: Set(self.room_heating_setpoint),
+            room_cooling_setpoint: Set(self.room_cooling_setpoint),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Skew of a timestamp taken `ago` before the call, allowing for the time the test takes
+    fn assert_skew(skew: i64, ago: Duration) {
+        let ago = ago.num_milliseconds();
+        assert!(skew >= ago && skew < ago + 1000, "skew {} for {} ms ago", skew, ago);
+    }
+
+    #[test]
+    fn event_timestamp_takes_the_newest_state() {
+        let now = Utc::now();
+        let timestamps = [30, 10, 20].map(|seconds| (now - Duration::seconds(seconds)).timestamp_millis());
+        let (timestamp, skew) = TimestampStrategy::Newest.event_timestamp(timestamps.to_vec());
+        assert_eq!(timestamp.timestamp_millis(), timestamps[1]);
+        assert_skew(skew, Duration::seconds(10));
+    }
+
+    #[test]
+    fn event_timestamp_takes_the_median_state() {
+        let now = Utc::now();
+        // One state updated late does not move the median
+        let timestamps = [3600, 12, 10, 11, 0].map(|seconds| (now - Duration::seconds(seconds)).timestamp_millis());
+        let (timestamp, skew) = TimestampStrategy::Median.event_timestamp(timestamps.to_vec());
+        assert_eq!(timestamp.timestamp_millis(), timestamps[3]);
+        assert_skew(skew, Duration::seconds(11));
+
+        // With an even number of states the later of the two middle ones
+        let (timestamp, _) = TimestampStrategy::Median.event_timestamp(vec![4000, 1000, 3000, 2000]);
+        assert_eq!(timestamp.timestamp_millis(), 3000);
+    }
+
+    #[test]
+    fn event_timestamp_falls_back_to_the_local_time() {
+        let before = Utc::now();
+        let (timestamp, skew) = TimestampStrategy::Local.event_timestamp(vec![1000, 2000]);
+        assert!(timestamp >= before && timestamp <= Utc::now());
+        assert_eq!(skew, 0);
+
+        let (timestamp, skew) = TimestampStrategy::Newest.event_timestamp(Vec::new());
+        assert!(timestamp >= before);
+        assert_eq!(skew, 0);
+    }
+
+    #[test]
+    fn event_timestamp_reports_source_clocks_ahead_as_negative_skew() {
+        let ahead = (Utc::now() + Duration::minutes(5)).timestamp_millis();
+        let (timestamp, skew) = TimestampStrategy::Newest.event_timestamp(vec![ahead]);
+        assert_eq!(timestamp.timestamp_millis(), ahead);
+        assert!(skew <= -Duration::minutes(5).num_milliseconds() + 1000 && skew >= -Duration::minutes(5).num_milliseconds());
+    }
+}
@@ -0,0 +1,284 @@
+use sea_orm_migration::prelude::*;
+
+/// Tables and indexes as created by the former `init.sql`. Everything is created only if missing,
+/// so databases set up by hand with `init.sql` are taken over, and older ones get the columns added since.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+CREATE TABLE IF NOT EXISTS heatpump (
+    event_timestamp TIMESTAMP WITH TIME ZONE PRIMARY KEY NOT NULL,
+    Clock_Skew_Ms BIGINT NOT NULL DEFAULT 0,
+    Ambient_ErrorNumber integer,
+    Ambient_State varchar(50),
+    Ambient_Temperature1h double precision,
+    Ambient_Temperature24h double precision,
+    Ambient_TemperatureCalculated double precision,
+    Boiler_HighTemp double precision,
+    Boiler_LowTemp double precision,
+    Boiler_MaxTemp double precision,
+    Boiler_State varchar(50),
+    Buffer_HighTemp double precision,
+    Buffer_LowTemp double precision,
+    Buffer_MaxTemp double precision,
+    Buffer_State varchar(50),
+    EManager_ErrorNumber integer,
+    HeatingCircuit_1_FlowTemp double precision,
+    HeatingCircuit_1_State varchar(50),
+    HeatingCircuit_2_FlowTemp double precision,
+    HeatingCircuit_2_State varchar(50),
+    Heatpump_ActualHeatingCapacity double precision,
+    Heatpump_CompressorRating double precision,
+    Heatpump_CurrentCop double precision,
+    Heatpump_ElectricEnergy double precision,
+    Heatpump_EnergySourceInletTemp double precision,
+    Heatpump_ErrorNumber double precision,
+    Heatpump_ErrorState varchar(50),
+    Heatpump_FlowlineTemp double precision,
+    Heatpump_HeatEnergy double precision,
+    Heatpump_InverterActualPower double precision,
+    Heatpump_OperatingState varchar(50),
+    Heatpump_RequestFlowTemp double precision,
+    Heatpump_RequestReturnTemp double precision,
+    Heatpump_RequestTempDiff double precision,
+    Heatpump_RequestType varchar(50),
+    Heatpump_ReturnLineTemp double precision,
+    Heatpump_State varchar(50),
+    Heatpump_VolumeSink double precision,
+    Heatpump_VolumeSourceFlow double precision,
+    Suspect_Fields JSONB NOT NULL DEFAULT '{}'::jsonb,
+    Conversion_Errors JSONB NOT NULL DEFAULT '{}'::jsonb
+);
+
+
+-- Create the temperature_data table
+CREATE TABLE IF NOT EXISTS temperature_data (
+   event_timestamp TIMESTAMP WITH TIME ZONE PRIMARY KEY NOT NULL,
+   Clock_Skew_Ms BIGINT NOT NULL DEFAULT 0,
+   data JSONB NOT NULL
+);
+
+-- Create index on temperature_data table
+CREATE INDEX IF NOT EXISTS idx_temperature_data_eventtime
+   ON temperature_data USING btree
+   (event_timestamp ASC NULLS LAST)
+   INCLUDE(event_timestamp)
+   TABLESPACE pg_default;
+
+-- Create index on heatpump table
+CREATE INDEX IF NOT EXISTS idx_eventtime
+    ON heatpump USING btree
+    (event_timestamp ASC NULLS LAST)
+    INCLUDE(event_timestamp)
+    WITH (deduplicate_items=True)
+    TABLESPACE pg_default;
+
+-- Every instance of the cascaded modules, module_index starts at 1.
+-- The heatpump table keeps the first instances for existing queries.
+CREATE TABLE IF NOT EXISTS heatpump_unit (
+    event_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    module_index SMALLINT NOT NULL,
+    error_state varchar(50),
+    error_number double precision,
+    state varchar(50),
+    operating_state varchar(50),
+    flowline_temp double precision,
+    return_line_temp double precision,
+    volume_sink double precision,
+    energy_source_inlet_temp double precision,
+    energy_source_outlet_temp double precision,
+    volume_source_flow double precision,
+    compressor_rating double precision,
+    actual_heating_capacity double precision,
+    inverter_actual_power double precision,
+    current_cop double precision,
+    request_type varchar(50),
+    request_flow_temp double precision,
+    request_return_temp double precision,
+    request_temp_diff double precision,
+    second_stage_active boolean,
+    electric_energy double precision,
+    heat_energy double precision,
+    PRIMARY KEY (event_timestamp, module_index)
+);
+
+CREATE TABLE IF NOT EXISTS boiler (
+    event_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    module_index SMALLINT NOT NULL,
+    error_number integer,
+    state varchar(50),
+    high_temp double precision,
+    low_temp double precision,
+    circulation_temp double precision,
+    circulation_pump_active boolean,
+    max_temp double precision,
+    PRIMARY KEY (event_timestamp, module_index)
+);
+
+CREATE TABLE IF NOT EXISTS buffer (
+    event_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    module_index SMALLINT NOT NULL,
+    error_number integer,
+    state varchar(50),
+    high_temp double precision,
+    low_temp double precision,
+    request_type varchar(50),
+    request_flow_temp double precision,
+    request_return_temp double precision,
+    request_temp_diff double precision,
+    request_capacity double precision,
+    max_temp double precision,
+    PRIMARY KEY (event_timestamp, module_index)
+);
+
+CREATE TABLE IF NOT EXISTS solar_module (
+    event_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    module_index SMALLINT NOT NULL,
+    error_number integer,
+    state varchar(50),
+    collector_temp double precision,
+    buffer_1_temp double precision,
+    buffer_2_temp double precision,
+    max_buffer_temp double precision,
+    buffer_changeover_temp double precision,
+    PRIMARY KEY (event_timestamp, module_index)
+);
+
+CREATE TABLE IF NOT EXISTS heating_circuit (
+    event_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    module_index SMALLINT NOT NULL,
+    error_number integer,
+    state varchar(50),
+    flow_temp double precision,
+    return_temp double precision,
+    room_temp double precision,
+    flow_setpoint double precision,
+    operating_mode varchar(50),
+    flow_offset double precision,
+    room_heating_setpoint double precision,
+    room_cooling_setpoint double precision,
+    PRIMARY KEY (event_timestamp, module_index)
+);
+
+-- Power flows of the inverter in W, filled when PV_ENABLED is set
+CREATE TABLE IF NOT EXISTS pv_data (
+    event_timestamp TIMESTAMP WITH TIME ZONE PRIMARY KEY NOT NULL,
+    clock_skew_ms BIGINT NOT NULL DEFAULT 0,
+    battery_power BIGINT NOT NULL,
+    battery_percentage double precision NOT NULL,
+    grid BIGINT NOT NULL,
+    home BIGINT NOT NULL,
+    pv BIGINT NOT NULL,
+    wallbox BIGINT NOT NULL
+);
+
+-- Charging sessions of the wallbox detected from pv_data, energies in Wh
+CREATE TABLE IF NOT EXISTS charging_session (
+    started_at TIMESTAMP WITH TIME ZONE PRIMARY KEY NOT NULL,
+    ended_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    energy_wh double precision NOT NULL,
+    pv_energy_wh double precision NOT NULL,
+    battery_energy_wh double precision NOT NULL,
+    grid_energy_wh double precision NOT NULL,
+    peak_power BIGINT NOT NULL
+);
+
+-- Databases created before quality flags were recorded
+ALTER TABLE heatpump ADD COLUMN IF NOT EXISTS Suspect_Fields JSONB NOT NULL DEFAULT '{}'::jsonb;
+
+-- Databases created before source timestamps were used
+ALTER TABLE heatpump ADD COLUMN IF NOT EXISTS Clock_Skew_Ms BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE temperature_data ADD COLUMN IF NOT EXISTS Clock_Skew_Ms BIGINT NOT NULL DEFAULT 0;
+
+-- Databases created before partial samples were stored
+ALTER TABLE heatpump ADD COLUMN IF NOT EXISTS Conversion_Errors JSONB NOT NULL DEFAULT '{}'::jsonb;
+ALTER TABLE heatpump
+    ALTER COLUMN Ambient_State DROP NOT NULL,
+    ALTER COLUMN Ambient_TemperatureCalculated DROP NOT NULL,
+    ALTER COLUMN Boiler_HighTemp DROP NOT NULL,
+    ALTER COLUMN Boiler_LowTemp DROP NOT NULL,
+    ALTER COLUMN Boiler_MaxTemp DROP NOT NULL,
+    ALTER COLUMN Boiler_State DROP NOT NULL,
+    ALTER COLUMN Buffer_HighTemp DROP NOT NULL,
+    ALTER COLUMN Buffer_LowTemp DROP NOT NULL,
+    ALTER COLUMN Buffer_MaxTemp DROP NOT NULL,
+    ALTER COLUMN Buffer_State DROP NOT NULL,
+    ALTER COLUMN HeatingCircuit_1_FlowTemp DROP NOT NULL,
+    ALTER COLUMN HeatingCircuit_1_State DROP NOT NULL,
+    ALTER COLUMN HeatingCircuit_2_FlowTemp DROP NOT NULL,
+    ALTER COLUMN HeatingCircuit_2_State DROP NOT NULL,
+    ALTER COLUMN Heatpump_ActualHeatingCapacity DROP NOT NULL,
+    ALTER COLUMN Heatpump_CompressorRating DROP NOT NULL,
+    ALTER COLUMN Heatpump_CurrentCop DROP NOT NULL,
+    ALTER COLUMN Heatpump_ElectricEnergy DROP NOT NULL,
+    ALTER COLUMN Heatpump_EnergySourceInletTemp DROP NOT NULL,
+    ALTER COLUMN Heatpump_ErrorNumber DROP NOT NULL,
+    ALTER COLUMN Heatpump_ErrorState DROP NOT NULL,
+    ALTER COLUMN Heatpump_FlowlineTemp DROP NOT NULL,
+    ALTER COLUMN Heatpump_HeatEnergy DROP NOT NULL,
+    ALTER COLUMN Heatpump_InverterActualPower DROP NOT NULL,
+    ALTER COLUMN Heatpump_OperatingState DROP NOT NULL,
+    ALTER COLUMN Heatpump_RequestFlowTemp DROP NOT NULL,
+    ALTER COLUMN Heatpump_RequestReturnTemp DROP NOT NULL,
+    ALTER COLUMN Heatpump_RequestTempDiff DROP NOT NULL,
+    ALTER COLUMN Heatpump_RequestType DROP NOT NULL,
+    ALTER COLUMN Heatpump_ReturnLineTemp DROP NOT NULL,
+    ALTER COLUMN Heatpump_State DROP NOT NULL,
+    ALTER COLUMN Heatpump_VolumeSink DROP NOT NULL,
+    ALTER COLUMN Heatpump_VolumeSourceFlow DROP NOT NULL;
+
+-- Databases created before the full register set was read
+ALTER TABLE heatpump
+    ADD COLUMN IF NOT EXISTS Ambient_ErrorNumber integer,
+    ADD COLUMN IF NOT EXISTS Ambient_Temperature1h double precision,
+    ADD COLUMN IF NOT EXISTS Ambient_Temperature24h double precision,
+    ADD COLUMN IF NOT EXISTS EManager_ErrorNumber integer;
+ALTER TABLE heatpump_unit
+    ADD COLUMN IF NOT EXISTS energy_source_outlet_temp double precision,
+    ADD COLUMN IF NOT EXISTS second_stage_active boolean;
+ALTER TABLE boiler
+    ADD COLUMN IF NOT EXISTS error_number integer,
+    ADD COLUMN IF NOT EXISTS circulation_temp double precision,
+    ADD COLUMN IF NOT EXISTS circulation_pump_active boolean;
+ALTER TABLE buffer
+    ADD COLUMN IF NOT EXISTS error_number integer,
+    ADD COLUMN IF NOT EXISTS request_type varchar(50),
+    ADD COLUMN IF NOT EXISTS request_flow_temp double precision,
+    ADD COLUMN IF NOT EXISTS request_return_temp double precision,
+    ADD COLUMN IF NOT EXISTS request_temp_diff double precision,
+    ADD COLUMN IF NOT EXISTS request_capacity double precision;
+ALTER TABLE heating_circuit
+    ADD COLUMN IF NOT EXISTS error_number integer,
+    ADD COLUMN IF NOT EXISTS return_temp double precision,
+    ADD COLUMN IF NOT EXISTS room_temp double precision,
+    ADD COLUMN IF NOT EXISTS flow_setpoint double precision,
+    ADD COLUMN IF NOT EXISTS operating_mode varchar(50),
+    ADD COLUMN IF NOT EXISTS flow_offset double precision,
+    ADD COLUMN IF NOT EXISTS room_heating_setpoint double precision,
+    ADD COLUMN IF NOT EXISTS room_cooling_setpoint double precision;
+"#;
+
+const DOWN: &str = r#"
+DROP TABLE IF EXISTS charging_session;
+DROP TABLE IF EXISTS pv_data;
+DROP TABLE IF EXISTS heating_circuit;
+DROP TABLE IF EXISTS solar_module;
+DROP TABLE IF EXISTS buffer;
+DROP TABLE IF EXISTS boiler;
+DROP TABLE IF EXISTS heatpump_unit;
+DROP TABLE IF EXISTS temperature_data;
+DROP TABLE IF EXISTS heatpump;
+"#;
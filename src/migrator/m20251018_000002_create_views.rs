@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+/// Views over the stored samples for queries
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+-- One row per device and quantity of the sensor readings in temperature_data, readings stored
+-- before other quantities were recorded only have their temperature in `value`
+CREATE OR REPLACE VIEW sensor_quantity AS
+SELECT
+    t.event_timestamp,
+    reading->>'device' AS device,
+    quantity.key AS quantity,
+    quantity.value::double precision AS value
+FROM temperature_data t
+CROSS JOIN LATERAL jsonb_array_elements(t.data) AS reading
+CROSS JOIN LATERAL jsonb_each_text(
+    COALESCE(reading->'quantities', jsonb_build_object('temperature', reading->'value'))
+) AS quantity;
+
+-- Charging sessions summarized per month, used by `fetcherRS charging-summary`
+CREATE OR REPLACE VIEW charging_session_monthly AS
+SELECT
+    date_trunc('month', started_at) AS month,
+    count(*) AS sessions,
+    sum(energy_wh) / 1000 AS energy_kwh,
+    sum(pv_energy_wh) / 1000 AS pv_energy_kwh,
+    sum(battery_energy_wh) / 1000 AS battery_energy_kwh,
+    sum(grid_energy_wh) / 1000 AS grid_energy_kwh,
+    sum(pv_energy_wh) / NULLIF(sum(energy_wh), 0) AS pv_share
+FROM charging_session
+GROUP BY date_trunc('month', started_at);
+"#;
+
+const DOWN: &str = r#"
+DROP VIEW IF EXISTS charging_session_monthly;
+DROP VIEW IF EXISTS sensor_quantity;
+"#;
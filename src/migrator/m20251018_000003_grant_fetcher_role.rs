@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+/// Login role the service can use instead of the owner of the tables
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+DO
+$$
+BEGIN
+   IF NOT EXISTS (
+      SELECT FROM pg_catalog.pg_roles
+      WHERE rolname = 'fetcher'
+   ) THEN
+      CREATE ROLE fetcher LOGIN PASSWORD 'qwasyx';
+   END IF;
+END
+$$;
+
+GRANT DELETE,SELECT,INSERT ON heatpump TO fetcher;
+GRANT DELETE,SELECT,INSERT ON temperature_data TO fetcher;
+GRANT DELETE,SELECT,INSERT ON heatpump_unit, boiler, buffer, solar_module, heating_circuit TO fetcher;
+GRANT DELETE,SELECT,INSERT ON pv_data, charging_session TO fetcher;
+GRANT SELECT ON charging_session_monthly TO fetcher;
+GRANT SELECT ON sensor_quantity TO fetcher;
+"#;
+
+const DOWN: &str = r#"
+-- The role is kept, it may hold privileges in other databases
+REVOKE ALL ON heatpump, temperature_data, heatpump_unit, boiler, buffer, solar_module, heating_circuit,
+    pv_data, charging_session, charging_session_monthly, sensor_quantity FROM fetcher;
+"#;
@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+/// One row per device, quantity and timestamp instead of a JSONB array per timestamp, filled from the
+/// readings already stored in `temperature_data`. `sensor_quantity` reads from the new table afterwards.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+-- Devices of the device map, name, room and floor as of their latest reading
+CREATE TABLE IF NOT EXISTS sensor_device (
+    id varchar(100) PRIMARY KEY NOT NULL,
+    name varchar(100),
+    room varchar(100),
+    floor varchar(50)
+);
+
+CREATE TABLE IF NOT EXISTS sensor_reading (
+    event_timestamp TIMESTAMP WITH TIME ZONE NOT NULL,
+    device_id varchar(100) NOT NULL REFERENCES sensor_device (id),
+    quantity varchar(50) NOT NULL,
+    value double precision NOT NULL,
+    PRIMARY KEY (device_id, quantity, event_timestamp)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sensor_reading_eventtime
+    ON sensor_reading USING btree
+    (event_timestamp ASC NULLS LAST);
+
+INSERT INTO sensor_device (id, name, room, floor)
+SELECT DISTINCT ON (reading->>'device')
+    reading->>'device',
+    reading->>'name',
+    reading->>'room',
+    reading->>'floor'
+FROM temperature_data t
+CROSS JOIN LATERAL jsonb_array_elements(t.data) AS reading
+WHERE reading->>'device' IS NOT NULL
+ORDER BY reading->>'device', t.event_timestamp DESC
+ON CONFLICT DO NOTHING;
+
+INSERT INTO sensor_reading (event_timestamp, device_id, quantity, value)
+SELECT event_timestamp, device, quantity, value
+FROM sensor_quantity
+WHERE device IS NOT NULL AND value IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+CREATE OR REPLACE VIEW sensor_quantity AS
+SELECT
+    event_timestamp,
+    device_id::text AS device,
+    quantity::text AS quantity,
+    value
+FROM sensor_reading;
+
+GRANT SELECT,INSERT,UPDATE,DELETE ON sensor_device TO fetcher;
+GRANT DELETE,SELECT,INSERT ON sensor_reading TO fetcher;
+"#;
+
+// temperature_data is still written, so the view can go back to it without losing readings
+const DOWN: &str = r#"
+CREATE OR REPLACE VIEW sensor_quantity AS
+SELECT
+    t.event_timestamp,
+    reading->>'device' AS device,
+    quantity.key AS quantity,
+    quantity.value::double precision AS value
+FROM temperature_data t
+CROSS JOIN LATERAL jsonb_array_elements(t.data) AS reading
+CROSS JOIN LATERAL jsonb_each_text(
+    COALESCE(reading->'quantities', jsonb_build_object('temperature', reading->'value'))
+) AS quantity;
+
+DROP TABLE IF EXISTS sensor_reading;
+DROP TABLE IF EXISTS sensor_device;
+"#;
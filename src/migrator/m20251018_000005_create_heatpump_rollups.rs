@@ -0,0 +1,133 @@
+use sea_orm_migration::prelude::*;
+
+/// Rollup tables of the heat pump history maintained by the rollup job, for databases without TimescaleDB
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+-- Rows written since the last rollup run, late rows from the spool or backfill included
+ALTER TABLE heatpump ADD COLUMN IF NOT EXISTS inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now();
+CREATE INDEX IF NOT EXISTS idx_heatpump_insertedat
+    ON heatpump USING btree
+    (inserted_at ASC NULLS LAST);
+
+-- Min, max and avg of the numeric columns, the most frequent value of the state columns and the
+-- energy used since the previous bucket per bucket of raw heatpump rows
+CREATE TABLE IF NOT EXISTS heatpump_5min (
+    bucket TIMESTAMP WITH TIME ZONE PRIMARY KEY NOT NULL,
+    samples integer NOT NULL,
+    ambient_temperature1h_min double precision,
+    ambient_temperature1h_max double precision,
+    ambient_temperature1h_avg double precision,
+    ambient_temperature24h_min double precision,
+    ambient_temperature24h_max double precision,
+    ambient_temperature24h_avg double precision,
+    ambient_temperaturecalculated_min double precision,
+    ambient_temperaturecalculated_max double precision,
+    ambient_temperaturecalculated_avg double precision,
+    boiler_hightemp_min double precision,
+    boiler_hightemp_max double precision,
+    boiler_hightemp_avg double precision,
+    boiler_lowtemp_min double precision,
+    boiler_lowtemp_max double precision,
+    boiler_lowtemp_avg double precision,
+    boiler_maxtemp_min double precision,
+    boiler_maxtemp_max double precision,
+    boiler_maxtemp_avg double precision,
+    buffer_hightemp_min double precision,
+    buffer_hightemp_max double precision,
+    buffer_hightemp_avg double precision,
+    buffer_lowtemp_min double precision,
+    buffer_lowtemp_max double precision,
+    buffer_lowtemp_avg double precision,
+    buffer_maxtemp_min double precision,
+    buffer_maxtemp_max double precision,
+    buffer_maxtemp_avg double precision,
+    heatingcircuit_1_flowtemp_min double precision,
+    heatingcircuit_1_flowtemp_max double precision,
+    heatingcircuit_1_flowtemp_avg double precision,
+    heatingcircuit_2_flowtemp_min double precision,
+    heatingcircuit_2_flowtemp_max double precision,
+    heatingcircuit_2_flowtemp_avg double precision,
+    heatpump_actualheatingcapacity_min double precision,
+    heatpump_actualheatingcapacity_max double precision,
+    heatpump_actualheatingcapacity_avg double precision,
+    heatpump_compressorrating_min double precision,
+    heatpump_compressorrating_max double precision,
+    heatpump_compressorrating_avg double precision,
+    heatpump_currentcop_min double precision,
+    heatpump_currentcop_max double precision,
+    heatpump_currentcop_avg double precision,
+    heatpump_energysourceinlettemp_min double precision,
+    heatpump_energysourceinlettemp_max double precision,
+    heatpump_energysourceinlettemp_avg double precision,
+    heatpump_flowlinetemp_min double precision,
+    heatpump_flowlinetemp_max double precision,
+    heatpump_flowlinetemp_avg double precision,
+    heatpump_inverteractualpower_min double precision,
+    heatpump_inverteractualpower_max double precision,
+    heatpump_inverteractualpower_avg double precision,
+    heatpump_requestflowtemp_min double precision,
+    heatpump_requestflowtemp_max double precision,
+    heatpump_requestflowtemp_avg double precision,
+    heatpump_requestreturntemp_min double precision,
+    heatpump_requestreturntemp_max double precision,
+    heatpump_requestreturntemp_avg double precision,
+    heatpump_requesttempdiff_min double precision,
+    heatpump_requesttempdiff_max double precision,
+    heatpump_requesttempdiff_avg double precision,
+    heatpump_returnlinetemp_min double precision,
+    heatpump_returnlinetemp_max double precision,
+    heatpump_returnlinetemp_avg double precision,
+    heatpump_volumesink_min double precision,
+    heatpump_volumesink_max double precision,
+    heatpump_volumesink_avg double precision,
+    heatpump_volumesourceflow_min double precision,
+    heatpump_volumesourceflow_max double precision,
+    heatpump_volumesourceflow_avg double precision,
+    ambient_state varchar(50),
+    boiler_state varchar(50),
+    buffer_state varchar(50),
+    heatingcircuit_1_state varchar(50),
+    heatingcircuit_2_state varchar(50),
+    heatpump_errorstate varchar(50),
+    heatpump_operatingstate varchar(50),
+    heatpump_requesttype varchar(50),
+    heatpump_state varchar(50),
+    heat_energy_delta double precision,
+    electric_energy_delta double precision
+);
+
+CREATE TABLE IF NOT EXISTS heatpump_hourly (LIKE heatpump_5min INCLUDING ALL);
+CREATE TABLE IF NOT EXISTS heatpump_daily (LIKE heatpump_5min INCLUDING ALL);
+
+-- Raw rows inserted up to this time are contained in the rollups
+CREATE TABLE IF NOT EXISTS rollup_state (
+    name varchar(50) PRIMARY KEY NOT NULL,
+    rolled_up_until TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+GRANT DELETE,SELECT,INSERT,UPDATE ON heatpump_5min, heatpump_hourly, heatpump_daily, rollup_state TO fetcher;
+"#;
+
+const DOWN: &str = r#"
+DROP TABLE IF EXISTS rollup_state;
+DROP TABLE IF EXISTS heatpump_daily;
+DROP TABLE IF EXISTS heatpump_hourly;
+DROP TABLE IF EXISTS heatpump_5min;
+DROP INDEX IF EXISTS idx_heatpump_insertedat;
+ALTER TABLE heatpump DROP COLUMN IF EXISTS inserted_at;
+"#;
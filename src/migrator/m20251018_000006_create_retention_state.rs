@@ -0,0 +1,31 @@
+use sea_orm_migration::prelude::*;
+
+/// Time up to which the retention job deleted raw rows, older buckets are no longer recomputed by the rollup job
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+CREATE TABLE IF NOT EXISTS retention_state (
+    table_name varchar(50) PRIMARY KEY NOT NULL,
+    deleted_before TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+GRANT DELETE,SELECT,INSERT,UPDATE ON retention_state TO fetcher;
+"#;
+
+const DOWN: &str = r#"
+DROP TABLE IF EXISTS retention_state;
+"#;
@@ -0,0 +1,105 @@
+use sea_orm_migration::prelude::*;
+
+/// `temperature_data` becomes a view over `sensor_reading`, so every sample is only written once.
+/// The clock skew of a sample moves to its readings, readings only stored as JSON are copied over first.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+ALTER TABLE sensor_reading ADD COLUMN IF NOT EXISTS clock_skew_ms BIGINT NOT NULL DEFAULT 0;
+
+INSERT INTO sensor_device (id, name, room, floor)
+SELECT DISTINCT ON (reading->>'device')
+    reading->>'device',
+    reading->>'name',
+    reading->>'room',
+    reading->>'floor'
+FROM temperature_data t
+CROSS JOIN LATERAL jsonb_array_elements(t.data) AS reading
+WHERE reading->>'device' IS NOT NULL
+ORDER BY reading->>'device', t.event_timestamp DESC
+ON CONFLICT DO NOTHING;
+
+INSERT INTO sensor_reading (event_timestamp, device_id, quantity, value, clock_skew_ms)
+SELECT
+    t.event_timestamp,
+    reading->>'device',
+    quantity.key,
+    quantity.value::double precision,
+    t.clock_skew_ms
+FROM temperature_data t
+CROSS JOIN LATERAL jsonb_array_elements(t.data) AS reading
+CROSS JOIN LATERAL jsonb_each_text(
+    COALESCE(reading->'quantities', jsonb_build_object('temperature', reading->'value'))
+) AS quantity
+WHERE reading->>'device' IS NOT NULL AND quantity.value IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+UPDATE sensor_reading r
+SET clock_skew_ms = t.clock_skew_ms
+FROM temperature_data t
+WHERE t.event_timestamp = r.event_timestamp AND t.clock_skew_ms <> 0;
+
+DROP TABLE temperature_data;
+
+-- Same JSON as written before, name, room and floor as currently set in sensor_device
+CREATE VIEW temperature_data AS
+SELECT
+    event_timestamp,
+    max(clock_skew_ms) AS clock_skew_ms,
+    jsonb_agg(reading ORDER BY device_id) AS data
+FROM (
+    SELECT
+        r.event_timestamp,
+        r.device_id,
+        max(r.clock_skew_ms) AS clock_skew_ms,
+        jsonb_strip_nulls(jsonb_build_object(
+            'device', r.device_id,
+            'value', max(r.value) FILTER (WHERE r.quantity = 'temperature'),
+            'quantities', jsonb_object_agg(r.quantity, r.value),
+            'name', d.name,
+            'room', d.room,
+            'floor', d.floor
+        )) AS reading
+    FROM sensor_reading r
+    JOIN sensor_device d ON d.id = r.device_id
+    GROUP BY r.event_timestamp, r.device_id, d.name, d.room, d.floor
+) readings
+GROUP BY event_timestamp;
+
+GRANT SELECT ON temperature_data TO fetcher;
+"#;
+
+const DOWN: &str = r#"
+CREATE TABLE temperature_data_restored AS
+SELECT event_timestamp, clock_skew_ms, data FROM temperature_data;
+
+DROP VIEW temperature_data;
+ALTER TABLE temperature_data_restored RENAME TO temperature_data;
+ALTER TABLE temperature_data
+    ADD PRIMARY KEY (event_timestamp),
+    ALTER COLUMN clock_skew_ms SET NOT NULL,
+    ALTER COLUMN clock_skew_ms SET DEFAULT 0,
+    ALTER COLUMN data SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_temperature_data_eventtime
+   ON temperature_data USING btree
+   (event_timestamp ASC NULLS LAST);
+
+ALTER TABLE sensor_reading DROP COLUMN IF EXISTS clock_skew_ms;
+
+GRANT DELETE,SELECT,INSERT ON temperature_data TO fetcher;
+"#;
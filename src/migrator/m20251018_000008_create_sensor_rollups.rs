@@ -0,0 +1,50 @@
+use sea_orm_migration::prelude::*;
+
+/// Rollup tables of the sensor readings maintained by the rollup job, so the retention job can prune `sensor_reading`
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(UP).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.get_connection().execute_unprepared(DOWN).await?;
+        Ok(())
+    }
+}
+
+const UP: &str = r#"
+-- Readings written since the last rollup run, late readings from the spool or backfill included
+ALTER TABLE sensor_reading ADD COLUMN IF NOT EXISTS inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now();
+CREATE INDEX IF NOT EXISTS idx_sensor_reading_insertedat
+    ON sensor_reading USING btree
+    (inserted_at ASC NULLS LAST);
+
+-- Number of readings, avg, min and max per bucket, device and quantity, named like the TimescaleDB aggregates
+CREATE TABLE IF NOT EXISTS sensor_reading_hourly (
+    bucket TIMESTAMP WITH TIME ZONE NOT NULL,
+    device_id varchar(100) NOT NULL,
+    quantity varchar(50) NOT NULL,
+    samples integer NOT NULL,
+    value double precision NOT NULL,
+    min_value double precision NOT NULL,
+    max_value double precision NOT NULL,
+    PRIMARY KEY (device_id, quantity, bucket)
+);
+
+CREATE TABLE IF NOT EXISTS sensor_reading_daily (LIKE sensor_reading_hourly INCLUDING ALL);
+
+GRANT DELETE,SELECT,INSERT,UPDATE ON sensor_reading_hourly, sensor_reading_daily TO fetcher;
+"#;
+
+const DOWN: &str = r#"
+DELETE FROM rollup_state WHERE name = 'sensor_reading';
+DROP TABLE IF EXISTS sensor_reading_daily;
+DROP TABLE IF EXISTS sensor_reading_hourly;
+DROP INDEX IF EXISTS idx_sensor_reading_insertedat;
+ALTER TABLE sensor_reading DROP COLUMN IF EXISTS inserted_at;
+"#;
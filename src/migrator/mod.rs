@@ -0,0 +1,32 @@
+use sea_orm_migration::prelude::*;
+
+mod m20251018_000001_create_tables;
+mod m20251018_000002_create_views;
+mod m20251018_000003_grant_fetcher_role;
+mod m20251018_000004_normalize_sensor_readings;
+mod m20251018_000005_create_heatpump_rollups;
+mod m20251018_000006_create_retention_state;
+mod m20251018_000007_temperature_data_view;
+mod m20251018_000008_create_sensor_rollups;
+mod m20251018_000009_create_rollup_skipped;
+
+/// Versioned schema migrations, applied ones are recorded in `seaql_migrations`.
+/// New schema changes get a new migration, released ones are never edited.
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20251018_000001_create_tables::Migration),
+            Box::new(m20251018_000002_create_views::Migration),
+            Box::new(m20251018_000003_grant_fetcher_role::Migration),
+            Box::new(m20251018_000004_normalize_sensor_readings::Migration),
+            Box::new(m20251018_000005_create_heatpump_rollups::Migration),
+            Box::new(m20251018_000006_create_retention_state::Migration),
+            Box::new(m20251018_000007_temperature_data_view::Migration),
+            Box::new(m20251018_000008_create_sensor_rollups::Migration),
+            Box::new(m20251018_000009_create_rollup_skipped::Migration),
+        ]
+    }
+}
@@ -0,0 +1,286 @@
+use crate::models::model_iobroker::{IoBrokerResponse, IoBrokerValue};
+use crate::register_map::{HOLDING_REGISTER_OFFSET, ModuleKind, RegisterDescription, RegisterMap};
+use chrono::Utc;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::time::timeout;
+use tokio_modbus::client::{Context, Reader, tcp};
+use tokio_modbus::{ExceptionCode, Slave};
+
+// A mapped state together with how it is stored in the registers
+type MappedRegister<'a> = (&'a str, &'a RegisterDescription);
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ModbusClientError {
+    ConnectionError(io::Error),
+    TransportError(tokio_modbus::Error),
+    ExceptionError(u16, ExceptionCode),
+    TimeoutError,
+}
+
+impl fmt::Display for ModbusClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModbusClientError::ConnectionError(e) => write!(f, "Connection error: {}", e),
+            ModbusClientError::TransportError(e) => write!(f, "Modbus transport error: {}", e),
+            ModbusClientError::ExceptionError(register, code) => {
+                write!(f, "Modbus exception at register {}: {}", register, code)
+            }
+            ModbusClientError::TimeoutError => write!(f, "Request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for ModbusClientError {}
+
+/// Reads the registers of the register map directly over Modbus TCP and presents them
+/// as ioBroker states holding the raw register value, so the regular mapper can be reused.
+#[derive(Clone)]
+pub struct LambdaModbusClient {
+    address: String,
+    unit_id: u8,
+    timeout: Duration,
+}
+
+impl LambdaModbusClient {
+    pub fn new(address: String, unit_id: u8) -> Self {
+        Self {
+            address,
+            unit_id,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub async fn fetch_data(&self, register_map: &RegisterMap) -> Result<IoBrokerResponse, ModbusClientError> {
+        println!("Fetching data from Modbus TCP: {}", self.address);
+        timeout(self.timeout, self.try_fetch_data(register_map))
+            .await
+            .map_err(|_| ModbusClientError::TimeoutError)?
+    }
+
+    async fn try_fetch_data(&self, register_map: &RegisterMap) -> Result<IoBrokerResponse, ModbusClientError> {
+        let socket_addr = lookup_host(&self.address)
+            .await
+            .map_err(ModbusClientError::ConnectionError)?
+            .next()
+            .ok_or_else(|| {
+                ModbusClientError::ConnectionError(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Could not resolve {}", self.address),
+                ))
+            })?;
+        let mut context = tcp::connect_slave(socket_addr, Slave(self.unit_id))
+            .await
+            .map_err(ModbusClientError::ConnectionError)?;
+
+        let result = read_registers(&mut context, register_map).await;
+        // The controller only accepts a handful of parallel connections
+        let _ = tokio_modbus::client::Client::disconnect(&mut context).await;
+        result
+    }
+}
+
+async fn read_registers(context: &mut Context, register_map: &RegisterMap) -> Result<IoBrokerResponse, ModbusClientError> {
+    let timestamp = Utc::now().timestamp_millis();
+    let mut response = IoBrokerResponse::new();
+
+    for block in contiguous_blocks(&register_map.general_registers()) {
+        let words = read_block(context, block).await?;
+        insert_block(&mut response, block, &words, timestamp);
+    }
+
+    for kind in ModuleKind::ALL {
+        for instance in 0..register_map.instances(kind) {
+            let registers = register_map.instance_registers(kind, instance);
+            for (i, block) in contiguous_blocks(&registers).into_iter().enumerate() {
+                match read_block(context, block).await {
+                    Ok(words) => insert_block(&mut response, block, &words, timestamp),
+                    // Instances that are not installed reject their registers, the remaining
+                    // blocks of the instance are skipped
+                    Err(ModbusClientError::ExceptionError(_, ExceptionCode::IllegalDataAddress)) if i == 0 => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    Ok(response)
+}
+
+async fn read_block(context: &mut Context, block: &[MappedRegister<'_>]) -> Result<Vec<u16>, ModbusClientError> {
+    let first = register_number(&block[0]);
+    let count: u16 = block.iter().map(|(_, description)| description.words).sum();
+    context
+        .read_holding_registers(first - HOLDING_REGISTER_OFFSET, count)
+        .await
+        .map_err(ModbusClientError::TransportError)?
+        .map_err(|code| ModbusClientError::ExceptionError(first, code))
+}
+
+// The raw value is stored, scaling is left to the mapper
+fn insert_block(response: &mut IoBrokerResponse, block: &[MappedRegister<'_>], words: &[u16], timestamp: i64) {
+    let mut position = 0;
+    for (state, description) in block {
+        let raw = description.decode(&words[position..]);
+        position += description.words as usize;
+        response.insert(
+            state.to_string(),
+            IoBrokerValue {
+                val: raw.to_string(),
+                ack: Some(true),
+                ts: timestamp,
+                q: Some(0),
+                from: String::from("system.adapter.fetcher.modbus"),
+                user: String::new(),
+                lc: timestamp,
+            },
+        );
+    }
+}
+
+// Groups registers that directly follow each other so they can be read with a single request
+fn contiguous_blocks<'a, 'b>(registers: &'a [MappedRegister<'b>]) -> Vec<&'a [MappedRegister<'b>]> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for i in 1..=registers.len() {
+        let is_contiguous = i < registers.len()
+            && register_number(&registers[i - 1]).checked_add(registers[i - 1].1.words) == Some(register_number(&registers[i]));
+        if !is_contiguous {
+            blocks.push(&registers[start..i]);
+            start = i;
+        }
+    }
+    blocks
+}
+
+// Only states with a register are handed out by the register map
+fn register_number((_, description): &MappedRegister<'_>) -> u16 {
+    description.register.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::{map_lamda_data, MappingMode, QualityRules, TimestampStrategy, ValueScaling};
+    use crate::models::model_lambda::HeatPumpStateEnum;
+    use std::collections::HashMap;
+    use std::future;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+    use tokio_modbus::server::Service;
+    use tokio_modbus::server::tcp::{Server, accept_tcp_connection};
+    use tokio_modbus::{Request, Response};
+
+    // Lambda controller stand-in with the ambient, E-Manager and first heat pump registers,
+    // every other register is rejected like on a controller without those modules
+    #[derive(Clone)]
+    struct LambdaStandIn {
+        registers: Arc<HashMap<u16, u16>>,
+        requests: Arc<Mutex<Vec<(u16, u16)>>>,
+    }
+
+    impl Service for LambdaStandIn {
+        type Request = Request<'static>;
+        type Response = Response;
+        type Exception = ExceptionCode;
+        type Future = future::Ready<Result<Response, ExceptionCode>>;
+
+        fn call(&self, request: Request<'static>) -> Self::Future {
+            let Request::ReadHoldingRegisters(address, count) = request else {
+                return future::ready(Err(ExceptionCode::IllegalFunction));
+            };
+            self.requests.lock().unwrap().push((address, count));
+            let words: Option<Vec<u16>> = (address..address + count)
+                .map(|address| self.registers.get(&address).copied())
+                .collect();
+            future::ready(words.map(Response::ReadHoldingRegisters).ok_or(ExceptionCode::IllegalDataAddress))
+        }
+    }
+
+    async fn start_stand_in(values: &[(u16, u16)]) -> (String, Arc<Mutex<Vec<(u16, u16)>>>) {
+        let mut registers: HashMap<u16, u16> = (0..5).chain(100..105).chain(1000..1024).map(|address| (address, 0)).collect();
+        registers.extend(values.iter().copied());
+        let service = LambdaStandIn {
+            registers: Arc::new(registers),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let requests = service.requests.clone();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let on_connected = |stream, socket_addr| {
+                let service = service.clone();
+                async move { accept_tcp_connection(stream, socket_addr, move |_| Ok(Some(service.clone()))) }
+            };
+            let _ = Server::new(listener).serve(&on_connected, |e| eprintln!("Modbus stand-in error: {}", e)).await;
+        });
+        (address, requests)
+    }
+
+    #[tokio::test]
+    async fn fetch_data_reads_contiguous_registers_in_one_request() {
+        let (address, requests) = start_stand_in(&[]).await;
+        let register_map = RegisterMap::bundled().unwrap();
+        LambdaModbusClient::new(address, 1).fetch_data(&register_map).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        // Ambient, E-Manager, then the first heat pump in two blocks around the unused register 1014
+        assert_eq!(requests[..4], [(0, 5), (100, 5), (1000, 14), (1015, 9)]);
+    }
+
+    #[tokio::test]
+    async fn fetch_data_skips_instances_that_reject_their_registers() {
+        let (address, requests) = start_stand_in(&[]).await;
+        let register_map = RegisterMap::bundled().unwrap();
+        let response = LambdaModbusClient::new(address, 1).fetch_data(&register_map).await.unwrap();
+
+        assert!(response.contains_key(register_map.instance_state(ModuleKind::HeatPump, 0, "heat_energy")));
+        assert!(!response.contains_key(register_map.instance_state(ModuleKind::HeatPump, 1, "state")));
+        assert!(!response.contains_key(register_map.instance_state(ModuleKind::HeatingCircuit, 0, "state")));
+        // Only the first block of a missing instance is asked for
+        let requests = requests.lock().unwrap();
+        assert!(requests.contains(&(1100, 14)));
+        assert!(!requests.contains(&(1115, 9)));
+    }
+
+    #[tokio::test]
+    async fn fetch_data_values_are_decoded_and_scaled_by_the_mapper() {
+        let (address, _) = start_stand_in(&[
+            // Ambient calculated temperature, signed in 0.1 °C
+            (4, 0xFFF6),
+            // Heat pump state START_COMPRESSOR, flow line temperature in 0.01 °C
+            (1002, 5),
+            (1004, 3512),
+            // Electric and heat energy, 32-bit with the high word first
+            (1020, 0x0001),
+            (1021, 0x86A0),
+            (1022, 0x0004),
+            (1023, 0x93E0),
+        ])
+        .await;
+        let register_map = RegisterMap::bundled().unwrap();
+        let response = LambdaModbusClient::new(address, 1).fetch_data(&register_map).await.unwrap();
+        assert_eq!(response[register_map.state("ambient_temperature_calculated")].val, "-10");
+
+        let data = map_lamda_data(
+            &response,
+            &register_map,
+            &QualityRules::default(),
+            MappingMode::Strict,
+            ValueScaling::Raw,
+            TimestampStrategy::Newest,
+        )
+        .unwrap();
+        assert_eq!(data.ambient_temperature_calculated, Some(-1.0));
+        assert_eq!(data.heat_pumps.len(), 1);
+        let heat_pump = &data.heat_pumps[0];
+        assert_eq!(heat_pump.state, Some(HeatPumpStateEnum::StartCompressor));
+        assert_eq!(heat_pump.flowline_temp, Some(35.12));
+        assert_eq!(heat_pump.electric_energy, Some(100000.0));
+        assert_eq!(heat_pump.heat_energy, Some(300000.0));
+    }
+}
@@ -1,4 +1,5 @@
+pub mod model_charging;
 pub mod model_iobroker;
 pub mod model_lambda;
-pub mod model_temperature;
-mod model_pv;
\ No newline at end of file
+pub mod model_pv;
+pub mod model_sensor;
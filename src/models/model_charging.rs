@@ -0,0 +1,27 @@
+use std::fmt::{Display, Error, Formatter};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// One charging session of the wallbox with the energy split by the source it came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChargingSession {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub energy_wh: f64,
+    pub pv_energy_wh: f64,
+    pub battery_energy_wh: f64,
+    pub grid_energy_wh: f64,
+    pub peak_power: i64,
+}
+
+impl Display for ChargingSession {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "Started At: {}", self.started_at)?;
+        writeln!(f, "Ended At: {}", self.ended_at)?;
+        writeln!(f, "Energy: {:.0} Wh", self.energy_wh)?;
+        writeln!(f, "PV Energy: {:.0} Wh", self.pv_energy_wh)?;
+        writeln!(f, "Battery Energy: {:.0} Wh", self.battery_energy_wh)?;
+        writeln!(f, "Grid Energy: {:.0} Wh", self.grid_energy_wh)?;
+        writeln!(f, "Peak Power: {} W", self.peak_power)
+    }
+}
@@ -12,12 +12,14 @@ pub struct IoBrokerValue {
     #[serde(deserialize_with = "deserialize_string_or_number")]
     #[serde(rename = "val")]
     pub val: String,
-    #[serde(rename = "ack")]
-    pub ack: bool,
+    /// Not reported by every source, e.g. the simple-api getBulk endpoint
+    #[serde(rename = "ack", default)]
+    pub ack: Option<bool>,
     #[serde(rename = "ts")]
     pub ts: i64,
-    #[serde(rename = "q")]
-    pub q: i32,
+    /// Not reported by every source, e.g. the simple-api getBulk endpoint
+    #[serde(rename = "q", default)]
+    pub q: Option<i32>,
     #[serde(rename = "from")]
     pub from: String,
     #[serde(rename = "user")]
@@ -26,6 +28,65 @@ pub struct IoBrokerValue {
     pub lc: i64,
 }
 
+// Entry of a simple-api getBulk response, which only carries id, value, timestamp and ack.
+// States that do not exist are answered with a null value and no timestamp.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IoBrokerBulkValue {
+    pub id: String,
+    #[serde(deserialize_with = "deserialize_string_or_number")]
+    pub val: String,
+    #[serde(default)]
+    pub ts: Option<i64>,
+    #[serde(default)]
+    pub ack: Option<bool>,
+}
+
+impl From<IoBrokerBulkValue> for IoBrokerValue {
+    fn from(value: IoBrokerBulkValue) -> Self {
+        let ts = value.ts.unwrap_or_default();
+        IoBrokerValue {
+            val: value.val,
+            ack: value.ack,
+            ts,
+            q: None,
+            from: String::new(),
+            user: String::new(),
+            lc: ts,
+        }
+    }
+}
+
+// Entry of a simple-api query response, holding the logged values of one state from the history adapter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IoBrokerHistory {
+    pub target: String,
+    /// Pairs of value and timestamp in milliseconds
+    pub datapoints: Vec<(serde_json::Value, i64)>,
+}
+
+impl IoBrokerHistory {
+    /// Logged values as states, in the order of the response
+    pub fn into_values(self) -> Vec<IoBrokerValue> {
+        self
+            .datapoints
+            .into_iter()
+            .map(|(val, ts)| IoBrokerValue {
+                val: match val {
+                    serde_json::Value::String(val) => val,
+                    serde_json::Value::Null => String::new(),
+                    val => val.to_string(),
+                },
+                ack: Some(true),
+                ts,
+                q: None,
+                from: String::new(),
+                user: String::new(),
+                lc: ts,
+            })
+            .collect()
+    }
+}
+
 // Custom deserializer function that handles string, number or null
 fn deserialize_string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
@@ -37,7 +98,15 @@ where
         type Value = String;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            formatter.write_str("string, number, or null")
+            formatter.write_str("string, number, boolean, or null")
+        }
+
+        // Switches and relay states may be reported as booleans
+        fn visit_bool<E>(self, value: bool) -> Result<String, E>
+        where
+            E: serde::de::Error,
+        {
+            Ok(value.to_string())
         }
 
         fn visit_i64<E>(self, value: i64) -> Result<String, E>
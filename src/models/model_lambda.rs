@@ -1,386 +1,723 @@
+use crate::register_map::Unit;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use strum_macros::{Display, EnumIter};
+use std::collections::BTreeMap;
 use std::fmt::{Display, Error, Formatter};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LambdaData {
+    #[serde(rename = "EventTimestamp")]
+    pub event_timestamp: DateTime<Utc>,
+    /// Local time minus event timestamp in milliseconds
+    #[serde(rename = "ClockSkewMs")]
+    pub clock_skew_ms: i64,
+    #[serde(rename = "Ambient_ErrorNumber")]
+    pub ambient_error_number: Option<i32>,
     #[serde(rename = "Ambient_State")]
-    pub ambient_state: AmbientStateEnum,
+    pub ambient_state: Option<AmbientStateEnum>,
+    /// Average outdoor temperature over the last hour
+    #[serde(rename = "Ambient_Temperature1h")]
+    pub ambient_temperature_1h: Option<f64>,
+    /// Average outdoor temperature over the last 24 hours
+    #[serde(rename = "Ambient_Temperature24h")]
+    pub ambient_temperature_24h: Option<f64>,
     #[serde(rename = "Ambient_TemperatureCalculated")]
-    pub ambient_temperature_calculated: f64,
+    pub ambient_temperature_calculated: Option<f64>,
+    #[serde(rename = "EManager_ErrorNumber")]
+    pub emanager_error_number: Option<i32>,
     #[serde(rename = "EManager_OperatingState")]
-    pub emanager_operating_state: EManagerStateEnum,
+    pub emanager_operating_state: Option<EManagerStateEnum>,
     #[serde(rename = "EManager_ActualPower")]
-    pub emanager_actual_power: f64,
+    pub emanager_actual_power: Option<f64>,
     #[serde(rename = "EManager_PVPower")]
-    pub emanager_pv_power: f64,
+    pub emanager_pv_power: Option<f64>,
     #[serde(rename = "EManager_PowerSetpoint")]
-    pub emanager_power_setpoint: f64,
-    #[serde(rename = "Heatpump_ErrorState")]
-    pub heatpump_error_state: EManagerErrorStateEnum,
-    #[serde(rename = "Heatpump_ErrorNumber")]
-    pub heatpump_error_number: i32,
-    #[serde(rename = "Heatpump_State")]
-    pub heatpump_state: HeatPumpStateEnum,
-    #[serde(rename = "Heatpump_OperatingState")]
-    pub heatpump_operating_state: HeatPumpOperatingStateEnum,
-    #[serde(rename = "Heatpump_FlowlineTemp")]
-    pub heatpump_flowline_temp: f64,
-    #[serde(rename = "Heatpump_ReturnLineTemp")]
-    pub heatpump_return_line_temp: f64,
-    #[serde(rename = "Heatpump_VolumeSink")]
-    pub heatpump_volume_sink: f64,
-    #[serde(rename = "Heatpump_EnergySourceInletTemp")]
-    pub heatpump_energy_source_inlet_temp: f64,
-    #[serde(rename = "Heatpump_VolumeSourceFlow")]
-    pub heatpump_volume_source_flow: f64,
-    #[serde(rename = "Heatpump_CompressorRating")]
-    pub heatpump_compressor_rating: f64,
-    #[serde(rename = "Heatpump_ActualHeatingCapacity")]
-    pub heatpump_actual_heating_capacity: f64,
-    #[serde(rename = "Heatpump_InverterActualPower" )]
-    pub heatpump_inverter_actual_power: f64,
-    #[serde(rename = "Heatpump_CurrentCop")]
-    pub heatpump_current_cop: f64,
-    #[serde(rename = "Heatpump_RequestType")]
-    pub heatpump_request_type: HeatPumpRequestType,
-    #[serde(rename = "Heatpump_RequestFlowTemp")]
-    pub heatpump_request_flow_temp: f64,
-    #[serde(rename = "Heatpump_RequestReturnTemp")]
-    pub heatpump_request_return_temp: f64,
-    #[serde(rename = "Heatpump_RequestTempDiff")]
-    pub heatpump_request_temp_diff: f64,
-    #[serde(rename = "Heatpump_ElectricEnergy")]
-    pub heatpump_electric_energy: f64,
-    #[serde(rename = "Heatpump_HeatEnergy")]
-    pub heatpump_heat_energy: f64,
-    #[serde(rename = "Boiler_State")]
-    pub boiler_state: BoilerStateEnum,
-    #[serde(rename = "Boiler_HighTemp")]
-    pub boiler_high_temp: f64,
-    #[serde(rename = "Boiler_LowTemp")]
-    pub boiler_low_temp: f64,
-    #[serde(rename = "Boiler_MaxTemp")]
-    pub boiler_max_temp: f64,
-    #[serde(rename = "Buffer_State")]
-    pub buffer_state: BufferState,
-    #[serde(rename = "Buffer_HighTemp")]
-    pub buffer_high_temp: f64,
-    #[serde(rename = "Buffer_LowTemp")]
-    pub buffer_low_temp: f64,
-    #[serde(rename = "Buffer_MaxTemp")]
-    pub buffer_max_temp: f64,
-    #[serde(rename = "HeatingCircuit_1_State")]
-    pub heating_circuit_1_state: HeatingCircuitState,
-    #[serde(rename = "HeatingCircuit_2_State")]
-    pub heating_circuit_2_state: HeatingCircuitState,
-    #[serde(rename = "HeatingCircuit_1_FlowTemp")]
-    pub heating_circuit_1_flow_temp: f64,
-    #[serde(rename = "HeatingCircuit_2_FlowTemp")]
-    pub heating_circuit_2_flow_temp: f64,
+    pub emanager_power_setpoint: Option<f64>,
+    /// Cascaded modules found in the sample, ordered by their index
+    #[serde(rename = "Heatpumps")]
+    pub heat_pumps: Vec<HeatPumpData>,
+    #[serde(rename = "Boilers")]
+    pub boilers: Vec<BoilerData>,
+    #[serde(rename = "Buffers")]
+    pub buffers: Vec<BufferData>,
+    #[serde(rename = "SolarModules")]
+    pub solar_modules: Vec<SolarData>,
+    #[serde(rename = "HeatingCircuits")]
+    pub heating_circuits: Vec<HeatingCircuitData>,
+    /// Unit of each field from the register map, e.g. `ambient_temperature_calculated`
+    /// or `heatpump.flowline_temp` for module fields. Left out of spooled samples, as it repeats the register map.
+    #[serde(rename = "Units", skip_serializing, default)]
+    pub units: BTreeMap<String, Unit>,
+    /// State IDs that could not be read in tolerant mapping mode, with the error
+    #[serde(rename = "ConversionErrors")]
+    pub conversion_errors: BTreeMap<String, String>,
+    /// State IDs that broke a quality rule, with the reason
+    #[serde(rename = "SuspectFields")]
+    pub suspect_fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeatPumpData {
+    /// Number of the heat pump in the cascade, starting at 1
+    #[serde(rename = "Index")]
+    pub index: u8,
+    #[serde(rename = "ErrorState")]
+    pub error_state: Option<EManagerErrorStateEnum>,
+    #[serde(rename = "ErrorNumber")]
+    pub error_number: Option<i32>,
+    #[serde(rename = "State")]
+    pub state: Option<HeatPumpStateEnum>,
+    #[serde(rename = "OperatingState")]
+    pub operating_state: Option<HeatPumpOperatingStateEnum>,
+    #[serde(rename = "FlowlineTemp")]
+    pub flowline_temp: Option<f64>,
+    #[serde(rename = "ReturnLineTemp")]
+    pub return_line_temp: Option<f64>,
+    #[serde(rename = "VolumeSink")]
+    pub volume_sink: Option<f64>,
+    #[serde(rename = "EnergySourceInletTemp")]
+    pub energy_source_inlet_temp: Option<f64>,
+    #[serde(rename = "EnergySourceOutletTemp")]
+    pub energy_source_outlet_temp: Option<f64>,
+    #[serde(rename = "VolumeSourceFlow")]
+    pub volume_source_flow: Option<f64>,
+    #[serde(rename = "CompressorRating")]
+    pub compressor_rating: Option<f64>,
+    #[serde(rename = "ActualHeatingCapacity")]
+    pub actual_heating_capacity: Option<f64>,
+    #[serde(rename = "InverterActualPower")]
+    pub inverter_actual_power: Option<f64>,
+    #[serde(rename = "CurrentCop")]
+    pub current_cop: Option<f64>,
+    #[serde(rename = "RequestType")]
+    pub request_type: Option<HeatPumpRequestType>,
+    #[serde(rename = "RequestFlowTemp")]
+    pub request_flow_temp: Option<f64>,
+    #[serde(rename = "RequestReturnTemp")]
+    pub request_return_temp: Option<f64>,
+    #[serde(rename = "RequestTempDiff")]
+    pub request_temp_diff: Option<f64>,
+    /// Relay of the second heating stage is switched on
+    #[serde(rename = "SecondStageActive")]
+    pub second_stage_active: Option<bool>,
+    #[serde(rename = "ElectricEnergy")]
+    pub electric_energy: Option<f64>,
+    #[serde(rename = "HeatEnergy")]
+    pub heat_energy: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoilerData {
+    /// Number of the boiler, starting at 1
+    #[serde(rename = "Index")]
+    pub index: u8,
+    #[serde(rename = "ErrorNumber")]
+    pub error_number: Option<i32>,
+    #[serde(rename = "State")]
+    pub state: Option<BoilerStateEnum>,
+    #[serde(rename = "HighTemp")]
+    pub high_temp: Option<f64>,
+    #[serde(rename = "LowTemp")]
+    pub low_temp: Option<f64>,
+    #[serde(rename = "CirculationTemp")]
+    pub circulation_temp: Option<f64>,
+    #[serde(rename = "CirculationPumpActive")]
+    pub circulation_pump_active: Option<bool>,
+    #[serde(rename = "MaxTemp")]
+    pub max_temp: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferData {
+    /// Number of the buffer, starting at 1
+    #[serde(rename = "Index")]
+    pub index: u8,
+    #[serde(rename = "ErrorNumber")]
+    pub error_number: Option<i32>,
+    #[serde(rename = "State")]
+    pub state: Option<BufferState>,
+    #[serde(rename = "HighTemp")]
+    pub high_temp: Option<f64>,
+    #[serde(rename = "LowTemp")]
+    pub low_temp: Option<f64>,
+    /// Request the buffer sends to the heat pumps
+    #[serde(rename = "RequestType")]
+    pub request_type: Option<HeatPumpRequestType>,
+    #[serde(rename = "RequestFlowTemp")]
+    pub request_flow_temp: Option<f64>,
+    #[serde(rename = "RequestReturnTemp")]
+    pub request_return_temp: Option<f64>,
+    #[serde(rename = "RequestTempDiff")]
+    pub request_temp_diff: Option<f64>,
+    #[serde(rename = "RequestCapacity")]
+    pub request_capacity: Option<f64>,
+    #[serde(rename = "MaxTemp")]
+    pub max_temp: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SolarData {
+    /// Number of the solar module, starting at 1
+    #[serde(rename = "Index")]
+    pub index: u8,
+    #[serde(rename = "ErrorNumber")]
+    pub error_number: Option<i32>,
+    #[serde(rename = "State")]
+    pub state: Option<SolarStateEnum>,
+    #[serde(rename = "CollectorTemp")]
+    pub collector_temp: Option<f64>,
+    #[serde(rename = "Buffer1Temp")]
+    pub buffer_1_temp: Option<f64>,
+    #[serde(rename = "Buffer2Temp")]
+    pub buffer_2_temp: Option<f64>,
+    #[serde(rename = "MaxBufferTemp")]
+    pub max_buffer_temp: Option<f64>,
+    /// Temperature at which the module switches from buffer 1 to buffer 2
+    #[serde(rename = "BufferChangeoverTemp")]
+    pub buffer_changeover_temp: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeatingCircuitData {
+    /// Number of the heating circuit, starting at 1
+    #[serde(rename = "Index")]
+    pub index: u8,
+    #[serde(rename = "ErrorNumber")]
+    pub error_number: Option<i32>,
+    #[serde(rename = "State")]
+    pub state: Option<HeatingCircuitState>,
+    #[serde(rename = "FlowTemp")]
+    pub flow_temp: Option<f64>,
+    #[serde(rename = "ReturnTemp")]
+    pub return_temp: Option<f64>,
+    #[serde(rename = "RoomTemp")]
+    pub room_temp: Option<f64>,
+    #[serde(rename = "FlowSetpoint")]
+    pub flow_setpoint: Option<f64>,
+    #[serde(rename = "OperatingMode")]
+    pub operating_mode: Option<HeatingCircuitMode>,
+    /// Offset added to the calculated flow temperature
+    #[serde(rename = "FlowOffset")]
+    pub flow_offset: Option<f64>,
+    #[serde(rename = "RoomHeatingSetpoint")]
+    pub room_heating_setpoint: Option<f64>,
+    #[serde(rename = "RoomCoolingSetpoint")]
+    pub room_cooling_setpoint: Option<f64>,
 }
 
 impl Display for LambdaData {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(),Error> {
+        let units = &self.units;
         writeln!(f, "===== Lambda Data Report =====")?;
+        writeln!(f, "Reported at: {} (clock skew {} ms)", self.event_timestamp, self.clock_skew_ms)?;
 
         // Ambient section
         writeln!(f, "\n[Ambient]")?;
-        writeln!(f, "State: {:?}", self.ambient_state)?;
-        writeln!(f, "Temperature: {:.1}°C", self.ambient_temperature_calculated)?;
+        writeln!(f, "State: {}", or_na(self.ambient_state, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.ambient_error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
+        }
+        writeln!(f, "Temperature: {}", measured(self.ambient_temperature_calculated, 1, units, "ambient_temperature_calculated"))?;
+        writeln!(f, "Average 1h: {}", measured(self.ambient_temperature_1h, 1, units, "ambient_temperature_1h"))?;
+        writeln!(f, "Average 24h: {}", measured(self.ambient_temperature_24h, 1, units, "ambient_temperature_24h"))?;
 
         // Energy Manager section
         writeln!(f, "\n[Energy Manager]")?;
-        writeln!(f, "Operating State: {:?}", self.emanager_operating_state)?;
-        writeln!(f, "Actual Power: {:.2} kW", self.emanager_actual_power)?;
-        writeln!(f, "PV Power: {:.2} kW", self.emanager_pv_power)?;
-        writeln!(f, "Power Setpoint: {:.2} kW", self.emanager_power_setpoint)?;
-
-        // Heat Pump section
-        writeln!(f, "\n[Heat Pump]")?;
-        writeln!(f, "State: {:?}", self.heatpump_state)?;
-        writeln!(f, "Operating State: {:?}", self.heatpump_operating_state)?;
-        writeln!(f, "Error State: {:?}", self.heatpump_error_state)?;
-        if self.heatpump_error_number != 0 {
-            writeln!(f, "Error Number: {}", self.heatpump_error_number)?;
+        writeln!(f, "Operating State: {}", or_na(self.emanager_operating_state, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.emanager_error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
+        }
+        writeln!(f, "Actual Power: {}", measured(self.emanager_actual_power, 2, units, "emanager_actual_power"))?;
+        writeln!(f, "PV Power: {}", measured(self.emanager_pv_power, 2, units, "emanager_pv_power"))?;
+        writeln!(f, "Power Setpoint: {}", measured(self.emanager_power_setpoint, 2, units, "emanager_power_setpoint"))?;
+
+        for heat_pump in &self.heat_pumps {
+            heat_pump.report(f, units)?;
+        }
+        for boiler in &self.boilers {
+            boiler.report(f, units)?;
+        }
+        for buffer in &self.buffers {
+            buffer.report(f, units)?;
+        }
+        for solar in &self.solar_modules {
+            solar.report(f, units)?;
+        }
+        for heating_circuit in &self.heating_circuits {
+            heating_circuit.report(f, units)?;
+        }
+
+        if !self.conversion_errors.is_empty() {
+            writeln!(f, "\n[Conversion Errors]")?;
+            for (key, error) in &self.conversion_errors {
+                writeln!(f, "{}: {}", key, error)?;
+            }
+        }
+        if !self.suspect_fields.is_empty() {
+            writeln!(f, "\n[Suspect Values]")?;
+            for (key, reason) in &self.suspect_fields {
+                writeln!(f, "{}: {}", key, reason)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HeatPumpData {
+    fn report(&self, f: &mut Formatter<'_>, units: &BTreeMap<String, Unit>) -> Result<(),Error> {
+        writeln!(f, "\n[Heat Pump {}]", self.index)?;
+        writeln!(f, "State: {}", or_na(self.state, |v| format!("{:?}", v)))?;
+        writeln!(f, "Operating State: {}", or_na(self.operating_state, |v| format!("{:?}", v)))?;
+        writeln!(f, "Error State: {}", or_na(self.error_state, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
+        }
+        writeln!(f, "Flow Temperature: {}", measured(self.flowline_temp, 1, units, "heatpump.flowline_temp"))?;
+        writeln!(f, "Return Temperature: {}", measured(self.return_line_temp, 1, units, "heatpump.return_line_temp"))?;
+        writeln!(f, "Volume Sink: {}", measured(self.volume_sink, 1, units, "heatpump.volume_sink"))?;
+        writeln!(f, "Source Inlet Temperature: {}", measured(self.energy_source_inlet_temp, 1, units, "heatpump.energy_source_inlet_temp"))?;
+        writeln!(f, "Source Outlet Temperature: {}", measured(self.energy_source_outlet_temp, 1, units, "heatpump.energy_source_outlet_temp"))?;
+        writeln!(f, "Source Flow: {}", measured(self.volume_source_flow, 1, units, "heatpump.volume_source_flow"))?;
+        writeln!(f, "Compressor Rating: {}", measured(self.compressor_rating, 1, units, "heatpump.compressor_rating"))?;
+        writeln!(f, "Actual Heating Capacity: {}", measured(self.actual_heating_capacity, 2, units, "heatpump.actual_heating_capacity"))?;
+        writeln!(f, "Inverter Power: {}", measured(self.inverter_actual_power, 2, units, "heatpump.inverter_actual_power"))?;
+        writeln!(f, "Current COP: {}", measured(self.current_cop, 2, units, "heatpump.current_cop"))?;
+        writeln!(f, "Request Type: {}", or_na(self.request_type, |v| format!("{:?}", v)))?;
+        writeln!(f, "Request Flow Temperature: {}", measured(self.request_flow_temp, 1, units, "heatpump.request_flow_temp"))?;
+        writeln!(f, "Request Return Temperature: {}", measured(self.request_return_temp, 1, units, "heatpump.request_return_temp"))?;
+        writeln!(f, "Request Temperature Difference: {}", measured(self.request_temp_diff, 1, units, "heatpump.request_temp_diff"))?;
+        writeln!(f, "Second Stage: {}", or_na(self.second_stage_active, on_off))?;
+        writeln!(f, "Electric Energy: {}", measured(self.electric_energy, 2, units, "heatpump.electric_energy"))?;
+        writeln!(f, "Heat Energy: {}", measured(self.heat_energy, 2, units, "heatpump.heat_energy"))?;
+        let calculated_cop = self.heat_energy.zip(self.electric_energy).map(|(heat, electric)| heat / electric);
+        writeln!(f, "Calculated COP: {}", or_na(calculated_cop, |v| format!("{:.2}", v)))?;
+        Ok(())
+    }
+}
+
+impl BoilerData {
+    fn report(&self, f: &mut Formatter<'_>, units: &BTreeMap<String, Unit>) -> Result<(),Error> {
+        writeln!(f, "\n[Boiler {}]", self.index)?;
+        writeln!(f, "State: {}", or_na(self.state, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
+        }
+        writeln!(f, "High Temperature: {}", measured(self.high_temp, 1, units, "boiler.high_temp"))?;
+        writeln!(f, "Low Temperature: {}", measured(self.low_temp, 1, units, "boiler.low_temp"))?;
+        writeln!(f, "Circulation Temperature: {}", measured(self.circulation_temp, 1, units, "boiler.circulation_temp"))?;
+        writeln!(f, "Circulation Pump: {}", or_na(self.circulation_pump_active, on_off))?;
+        writeln!(f, "Max Temperature: {}", measured(self.max_temp, 1, units, "boiler.max_temp"))?;
+        Ok(())
+    }
+}
+
+impl BufferData {
+    fn report(&self, f: &mut Formatter<'_>, units: &BTreeMap<String, Unit>) -> Result<(),Error> {
+        writeln!(f, "\n[Buffer {}]", self.index)?;
+        writeln!(f, "State: {}", or_na(self.state, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
+        }
+        writeln!(f, "High Temperature: {}", measured(self.high_temp, 1, units, "buffer.high_temp"))?;
+        writeln!(f, "Low Temperature: {}", measured(self.low_temp, 1, units, "buffer.low_temp"))?;
+        writeln!(f, "Request Type: {}", or_na(self.request_type, |v| format!("{:?}", v)))?;
+        writeln!(f, "Request Flow Temperature: {}", measured(self.request_flow_temp, 1, units, "buffer.request_flow_temp"))?;
+        writeln!(f, "Request Return Temperature: {}", measured(self.request_return_temp, 1, units, "buffer.request_return_temp"))?;
+        writeln!(f, "Request Temperature Difference: {}", measured(self.request_temp_diff, 1, units, "buffer.request_temp_diff"))?;
+        writeln!(f, "Request Capacity: {}", measured(self.request_capacity, 1, units, "buffer.request_capacity"))?;
+        writeln!(f, "Max Temperature: {}", measured(self.max_temp, 1, units, "buffer.max_temp"))?;
+        Ok(())
+    }
+}
+
+impl SolarData {
+    fn report(&self, f: &mut Formatter<'_>, units: &BTreeMap<String, Unit>) -> Result<(),Error> {
+        writeln!(f, "\n[Solar {}]", self.index)?;
+        writeln!(f, "State: {}", or_na(self.state, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
+        }
+        writeln!(f, "Collector Temperature: {}", measured(self.collector_temp, 1, units, "solar.collector_temp"))?;
+        writeln!(f, "Buffer 1 Temperature: {}", measured(self.buffer_1_temp, 1, units, "solar.buffer_1_temp"))?;
+        writeln!(f, "Buffer 2 Temperature: {}", measured(self.buffer_2_temp, 1, units, "solar.buffer_2_temp"))?;
+        writeln!(f, "Max Buffer Temperature: {}", measured(self.max_buffer_temp, 1, units, "solar.max_buffer_temp"))?;
+        writeln!(f, "Buffer Changeover Temperature: {}", measured(self.buffer_changeover_temp, 1, units, "solar.buffer_changeover_temp"))?;
+        Ok(())
+    }
+}
+
+impl HeatingCircuitData {
+    fn report(&self, f: &mut Formatter<'_>, units: &BTreeMap<String, Unit>) -> Result<(),Error> {
+        writeln!(f, "\n[Heating Circuit {}]", self.index)?;
+        writeln!(f, "State: {}", or_na(self.state, |v| format!("{:?}", v)))?;
+        writeln!(f, "Operating Mode: {}", or_na(self.operating_mode, |v| format!("{:?}", v)))?;
+        if let Some(error_number) = self.error_number.filter(|number| *number != 0) {
+            writeln!(f, "Error Number: {}", error_number)?;
         }
-        writeln!(f, "Flow Temperature: {:.1}°C", self.heatpump_flowline_temp)?;
-        writeln!(f, "Return Temperature: {:.1}°C", self.heatpump_return_line_temp)?;
-        writeln!(f, "Volume Sink: {:.1} l/h", self.heatpump_volume_sink)?;
-        writeln!(f, "Source Inlet Temperature: {:.1}°C", self.heatpump_energy_source_inlet_temp)?;
-        writeln!(f, "Source Flow: {:.1} l/h", self.heatpump_volume_source_flow)?;
-        writeln!(f, "Compressor Rating: {:.1}%", self.heatpump_compressor_rating)?;
-        writeln!(f, "Actual Heating Capacity: {:.2} kW", self.heatpump_actual_heating_capacity)?;
-        writeln!(f, "Inverter Power: {:.2} kW", self.heatpump_inverter_actual_power)?;
-        writeln!(f, "Current COP: {:.2}", self.heatpump_current_cop)?;
-        writeln!(f, "Request Type: {:?}", self.heatpump_request_type)?;
-        writeln!(f, "Request Flow Temperature: {:.1}°C", self.heatpump_request_flow_temp)?;
-        writeln!(f, "Request Return Temperature: {:.1}°C", self.heatpump_request_return_temp)?;
-        writeln!(f, "Request Temperature Difference: {:.1}K", self.heatpump_request_temp_diff)?;
-        writeln!(f, "Electric Energy: {:.2} kWh", self.heatpump_electric_energy)?;
-        writeln!(f, "Heat Energy: {:.2} kWh", self.heatpump_heat_energy)?;
-        writeln!(f, "Calculated COP: {:.2}", self.heatpump_heat_energy / self.heatpump_electric_energy)?;
-
-        // Boiler section
-        writeln!(f, "\n[Boiler]")?;
-        writeln!(f, "State: {:?}", self.boiler_state)?;
-        writeln!(f, "High Temperature: {:.1}°C", self.boiler_high_temp)?;
-        writeln!(f, "Low Temperature: {:.1}°C", self.boiler_low_temp)?;
-        writeln!(f, "Max Temperature: {:.1}°C", self.boiler_max_temp)?;
-
-        // Buffer section
-        writeln!(f, "\n[Buffer]")?;
-        writeln!(f, "State: {:?}", self.buffer_state)?;
-        writeln!(f, "High Temperature: {:.1}°C", self.buffer_high_temp)?;
-        writeln!(f, "Low Temperature: {:.1}°C", self.buffer_low_temp)?;
-        writeln!(f, "Max Temperature: {:.1}°C", self.buffer_max_temp)?;
-
-        // Heating Circuits section
-        writeln!(f, "\n[Heating Circuit 1]")?;
-        writeln!(f, "State: {:?}", self.heating_circuit_1_state)?;
-        writeln!(f, "Flow Temperature: {:.1}°C", self.heating_circuit_1_flow_temp)?;
-
-        writeln!(f, "\n[Heating Circuit 2]")?;
-        writeln!(f, "State: {:?}", self.heating_circuit_2_state)?;
-        writeln!(f, "Flow Temperature: {:.1}°C", self.heating_circuit_2_flow_temp)
+        writeln!(f, "Flow Temperature: {}", measured(self.flow_temp, 1, units, "heating_circuit.flow_temp"))?;
+        writeln!(f, "Return Temperature: {}", measured(self.return_temp, 1, units, "heating_circuit.return_temp"))?;
+        writeln!(f, "Room Temperature: {}", measured(self.room_temp, 1, units, "heating_circuit.room_temp"))?;
+        writeln!(f, "Flow Setpoint: {}", measured(self.flow_setpoint, 1, units, "heating_circuit.flow_setpoint"))?;
+        writeln!(f, "Flow Offset: {}", measured(self.flow_offset, 1, units, "heating_circuit.flow_offset"))?;
+        writeln!(f, "Room Heating Setpoint: {}", measured(self.room_heating_setpoint, 1, units, "heating_circuit.room_heating_setpoint"))?;
+        writeln!(f, "Room Cooling Setpoint: {}", measured(self.room_cooling_setpoint, 1, units, "heating_circuit.room_cooling_setpoint"))?;
+        Ok(())
+    }
+}
+
+// Formats a measured value with the unit the register map gives for the field
+fn measured(value: Option<f64>, decimals: usize, units: &BTreeMap<String, Unit>, field: &str) -> String {
+    or_na(value, |v| match units.get(field) {
+        Some(unit) => format!("{:.*} {}", decimals, v, unit),
+        None => format!("{:.*}", decimals, v),
+    })
+}
+
+// Formats an optional field, fields missing from a partial sample show as n/a
+fn or_na<T>(value: Option<T>, format: impl FnOnce(T) -> String) -> String {
+    value.map(format).unwrap_or_else(|| String::from("n/a"))
+}
+
+fn on_off(active: bool) -> String {
+    String::from(if active { "on" } else { "off" })
+}
+
+/// Lambda state enums whose Modbus register holds the numeric code of the variant
+pub trait LambdaEnum {
+    /// Decodes a register value, codes missing from the Lambda documentation become `Unknown`
+    fn from_code(code: u16) -> Self;
+}
+
+// Declares a Lambda state enum with its register codes. Codes are sparse for some
+// enums (e.g. HeatPumpStateEnum), so they are matched explicitly instead of by position,
+// and an `Unknown(u16)` variant keeps codes newer firmware may report.
+macro_rules! lambda_enum {
+    (pub enum $name:ident { $( $(#[$meta:meta])* $variant:ident = $code:literal ),+ $(,)? }) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+        pub enum $name {
+            $( $(#[$meta])* $variant, )+
+            #[serde(rename = "UNKNOWN")]
+            Unknown(u16),
+        }
+
+        impl LambdaEnum for $name {
+            fn from_code(code: u16) -> Self {
+                match code {
+                    $( $code => $name::$variant, )+
+                    other => $name::Unknown(other),
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+                match self {
+                    $( $name::$variant => write!(f, stringify!($variant)), )+
+                    $name::Unknown(code) => write!(f, "Unknown({})", code),
+                }
+            }
+        }
+    };
+}
+
+lambda_enum! {
+    pub enum AmbientStateEnum {
+        #[serde(rename = "OFF")]
+        Off = 0,
+        #[serde(rename = "AUTOMATIC")]
+        Automatic = 1,
+        #[serde(rename = "MANUAL")]
+        Manual = 2,
+        #[serde(rename = "ERROR")]
+        Error = 3
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter, Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum AmbientStateEnum {
-    #[serde(rename = "OFF")]
-    Off = 0,
-    #[serde(rename = "AUTOMATIC")]
-    Automatic = 1,
-    #[serde(rename = "MANUAL")]
-    Manual = 2,
-    #[serde(rename = "ERROR")]
-    Error = 3
+lambda_enum! {
+    pub enum EManagerStateEnum {
+        #[serde(rename = "OFF")]
+        Off = 0,
+        #[serde(rename = "AUTOMATIC")]
+        Automatic = 1,
+        #[serde(rename = "MANUAL")]
+        Manual = 2,
+        #[serde(rename = "ERROR")]
+        Error = 3,
+        #[serde(rename = "OFFLINE")]
+        Offline = 4,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum EManagerStateEnum {
-    #[serde(rename = "OFF")]
-    Off = 0,
-    #[serde(rename = "AUTOMATIC")]
-    Automatic = 1,
-    #[serde(rename = "MANUAL")]
-    Manual = 2,
-    #[serde(rename = "ERROR")]
-    Error = 3,
-    #[serde(rename = "OFFLINE")]
-    Offline = 4,
+lambda_enum! {
+    pub enum EManagerErrorStateEnum {
+        #[serde(rename = "NONE")]
+        None = 0,
+        #[serde(rename = "MESSAGE")]
+        Message = 1,
+        #[serde(rename = "WARNING")]
+        Warning = 2,
+        #[serde(rename = "ALARM")]
+        Alarm = 3,
+        #[serde(rename = "FAULT")]
+        Fault = 4,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum EManagerErrorStateEnum {
-    #[serde(rename = "NONE")]
-    None = 0,
-    #[serde(rename = "MESSAGE")]
-    Message = 1,
-    #[serde(rename = "WARNING")]
-    Warning = 2,
-    #[serde(rename = "ALARM")]
-    Alarm = 3,
-    #[serde(rename = "FAULT")]
-    Fault = 4,
+lambda_enum! {
+    pub enum HeatPumpStateEnum {
+        #[serde(rename = "INIT")]
+        Init = 0,
+        #[serde(rename = "REFERENCE")]
+        Reference = 1,
+        #[serde(rename = "RESTART_BLOCK")]
+        RestartBlock = 2,
+        #[serde(rename = "READY")]
+        Ready = 3,
+        #[serde(rename = "START_PUMPS")]
+        StartPumps = 4,
+        #[serde(rename = "START_COMPRESSOR")]
+        StartCompressor = 5,
+        #[serde(rename = "PRE_REGULATION")]
+        PreRegulation = 6,
+        #[serde(rename = "REGULATION")]
+        Regulation = 7,
+        #[serde(rename = "NOT_USED")]
+        NotUsed = 8,
+        #[serde(rename = "COOLING")]
+        Cooling = 9,
+        #[serde(rename = "DEFROSTING")]
+        Defrosting = 10,
+        #[serde(rename = "STOPPING")]
+        Stopping = 20,
+        #[serde(rename = "FAULT_LOCK")]
+        FaultLock = 30,
+        #[serde(rename = "ALARM_BLOCK")]
+        AlarmBlock = 31,
+        #[serde(rename = "ERROR_RESET")]
+        ErrorReset = 40,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum HeatPumpStateEnum {
-    #[serde(rename = "INIT")]
-    Init = 0,
-    #[serde(rename = "REFERENCE")]
-    Reference = 1,
-    #[serde(rename = "RESTART_BLOCK")]
-    RestartBlock = 2,
-    #[serde(rename = "READY")]
-    Ready = 3,
-    #[serde(rename = "START_PUMPS")]
-    StartPumps = 4,
-    #[serde(rename = "START_COMPRESSOR")]
-    StartCompressor = 5,
-    #[serde(rename = "PRE_REGULATION")]
-    PreRegulation = 6,
-    #[serde(rename = "REGULATION")]
-    Regulation = 7,
-    #[serde(rename = "NOT_USED")]
-    NotUsed = 8,
-    #[serde(rename = "COOLING")]
-    Cooling = 9,
-    #[serde(rename = "DEFROSTING")]
-    Defrosting = 10,
-    #[serde(rename = "STOPPING")]
-    Stopping = 20,
-    #[serde(rename = "FAULT_LOCK")]
-    FaultLock = 30,
-    #[serde(rename = "ALARM_BLOCK")]
-    AlarmBlock = 31,
-    #[serde(rename = "ERROR_RESET")]
-    ErrorReset = 40,
+lambda_enum! {
+    pub enum HeatPumpOperatingStateEnum {
+        #[serde(rename = "STANDBY")]
+        Stby = 0,
+        #[serde(rename = "CH")]
+        Ch = 1,
+        #[serde(rename = "DHW")]
+        Dhw = 2,
+        #[serde(rename = "CC")]
+        Cc = 3,
+        #[serde(rename = "CIRCULATE")]
+        Circulate = 4,
+        #[serde(rename = "DEFROST")]
+        Defrost = 5,
+        #[serde(rename = "OFF")]
+        Off = 6,
+        #[serde(rename = "FROST")]
+        Frost = 7,
+        #[serde(rename = "STBY_FROST")]
+        StbyFrost = 8,
+        #[serde(rename = "NOT_USED")]
+        NotUsed = 9,
+        #[serde(rename = "SUMMER")]
+        Summer = 10,
+        #[serde(rename = "HOLIDAY")]
+        Holiday = 11,
+        #[serde(rename = "ERROR")]
+        Error = 12,
+        #[serde(rename = "WARNING")]
+        Warning = 13,
+        #[serde(rename = "INFO_MESSAGE")]
+        InfoMessage = 14,
+        #[serde(rename = "TIME_BLOCK")]
+        TimeBlock = 15,
+        #[serde(rename = "RELEASE_BLOCK")]
+        ReleaseBlock = 16,
+        #[serde(rename = "MIN_TEMP_BLOCK")]
+        MintempBlock = 17,
+        #[serde(rename = "FIRMWARE_DOWNLOAD")]
+        FirmwareDownload = 18,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum HeatPumpOperatingStateEnum {
-    #[serde(rename = "STANDBY")]
-    Stby = 0,
-    #[serde(rename = "CH")]
-    Ch = 1,
-    #[serde(rename = "DHW")]
-    Dhw = 2,
-    #[serde(rename = "CC")]
-    Cc = 3,
-    #[serde(rename = "CIRCULATE")]
-    Circulate = 4,
-    #[serde(rename = "DEFROST")]
-    Defrost = 5,
-    #[serde(rename = "OFF")]
-    Off = 6,
-    #[serde(rename = "FROST")]
-    Frost = 7,
-    #[serde(rename = "STBY_FROST")]
-    StbyFrost = 8,
-    #[serde(rename = "NOT_USED")]
-    NotUsed = 9,
-    #[serde(rename = "SUMMER")]
-    Summer = 10,
-    #[serde(rename = "HOLIDAY")]
-    Holiday = 11,
-    #[serde(rename = "ERROR")]
-    Error = 12,
-    #[serde(rename = "WARNING")]
-    Warning = 13,
-    #[serde(rename = "INFO_MESSAGE")]
-    InfoMessage = 14,
-    #[serde(rename = "TIME_BLOCK")]
-    TimeBlock = 15,
-    #[serde(rename = "RELEASE_BLOCK")]
-    ReleaseBlock = 16,
-    #[serde(rename = "MIN_TEMP_BLOCK")]
-    MintempBlock = 17,
-    #[serde(rename = "FIRMWARE_DOWNLOAD")]
-    FirmwareDownload = 18,
+lambda_enum! {
+    pub enum HeatPumpRequestType {
+        #[serde(rename = "NO_REQUEST")]
+        NoRequest = 0,
+        #[serde(rename = "FLOW_PUMP_CIRCULATION")]
+        FlowPumpCirculation = 1,
+        #[serde(rename = "CENTRAL_HEATING")]
+        CentralHeating = 2,
+        #[serde(rename = "CENTRAL_COOLING")]
+        CentralCooling = 3,
+        #[serde(rename = "DOMESTIC_HOT_WATER")]
+        DomesticHotWater = 4,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum HeatPumpRequestType {
-    #[serde(rename = "NO_REQUEST")]
-    NoRequest = 0,
-    #[serde(rename = "FLOW_PUMP_CIRCULATION")]
-    FlowPumpCirculation = 1,
-    #[serde(rename = "CENTRAL_HEATING")]
-    CentralHeating = 2,
-    #[serde(rename = "CENTRAL_COOLING")]
-    CentralCooling = 3,
-    #[serde(rename = "DOMESTIC_HOT_WATER")]
-    DomesticHotWater = 4,
+lambda_enum! {
+    pub enum BoilerStateEnum {
+        #[serde(rename = "STBY")]
+        Stby = 0,
+        #[serde(rename = "DHW")]
+        Dhw = 1,
+        #[serde(rename = "LEGIO")]
+        Legio = 2,
+        #[serde(rename = "SUMMER")]
+        Summer = 3,
+        #[serde(rename = "FROST")]
+        Frost = 4,
+        #[serde(rename = "HOLIDAY")]
+        Holiday = 5,
+        #[serde(rename = "PRIO_STOP")]
+        PrioStop = 6,
+        #[serde(rename = "ERROR")]
+        Error = 7,
+        #[serde(rename = "OFF")]
+        Off = 8,
+        #[serde(rename = "PROMPT_DHW")]
+        PromptDhw = 9,
+        #[serde(rename = "TRAILING_STOP")]
+        TrailingStop = 10,
+        #[serde(rename = "TEMP_LOCK")]
+        TempLock = 11,
+        #[serde(rename = "STBY_FROST")]
+        StbyFrost = 12,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum BoilerStateEnum {
-    #[serde(rename = "STBY")]
-    Stby = 0,
-    #[serde(rename = "DHW")]
-    Dhw = 1,
-    #[serde(rename = "LEGIO")]
-    Legio = 2,
-    #[serde(rename = "SUMMER")]
-    Summer = 3,
-    #[serde(rename = "FROST")]
-    Frost = 4,
-    #[serde(rename = "HOLIDAY")]
-    Holiday = 5,
-    #[serde(rename = "PRIO_STOP")]
-    PrioStop = 6,
-    #[serde(rename = "ERROR")]
-    Error = 7,
-    #[serde(rename = "OFF")]
-    Off = 8,
-    #[serde(rename = "PROMPT_DHW")]
-    PromptDhw = 9,
-    #[serde(rename = "TRAILING_STOP")]
-    TrailingStop = 10,
-    #[serde(rename = "TEMP_LOCK")]
-    TempLock = 11,
-    #[serde(rename = "STBY_FROST")]
-    StbyFrost = 12,
+lambda_enum! {
+    pub enum BufferState {
+        #[serde(rename = "STBY")]
+        Stby = 0,
+        #[serde(rename = "HEATING")]
+        Heating = 1,
+        #[serde(rename = "COOLING")]
+        Cooling = 2,
+        #[serde(rename = "SUMMER")]
+        Summer = 3,
+        #[serde(rename = "FROST")]
+        Frost = 4,
+        #[serde(rename = "HOLIDAY")]
+        Holiday = 5,
+        #[serde(rename = "PRIO_STOP")]
+        PrioStop = 6,
+        #[serde(rename = "ERROR")]
+        Error = 7,
+        #[serde(rename = "OFF")]
+        Off = 8,
+        #[serde(rename = "STBY_FROST")]
+        StbyFrost = 9,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum BufferState {
-    #[serde(rename = "STBY")]
-    Stby = 0,
-    #[serde(rename = "HEATING")]
-    Heating = 1,
-    #[serde(rename = "COOLING")]
-    Cooling = 2,
-    #[serde(rename = "SUMMER")]
-    Summer = 3,
-    #[serde(rename = "FROST")]
-    Frost = 4,
-    #[serde(rename = "HOLIDAY")]
-    Holiday = 5,
-    #[serde(rename = "PRIO_STOP")]
-    PrioStop = 6,
-    #[serde(rename = "ERROR")]
-    Error = 7,
-    #[serde(rename = "OFF")]
-    Off = 8,
-    #[serde(rename = "STBY_FROST")]
-    StbyFrost = 9,
+lambda_enum! {
+    pub enum HeatingCircuitState {
+        #[serde(rename = "HEATING")]
+        Heating = 0,
+        #[serde(rename = "ECO")]
+        Eco = 1,
+        #[serde(rename = "COOLING")]
+        Cooling = 2,
+        #[serde(rename = "FLOOR_DRY")]
+        Floordry = 3,
+        #[serde(rename = "FROST")]
+        Frost = 4,
+        #[serde(rename = "MAX_TEMP")]
+        MaxTemp = 5,
+        #[serde(rename = "ERROR")]
+        Error = 6,
+        #[serde(rename = "SERVICE")]
+        Service = 7,
+        #[serde(rename = "HOLIDAY")]
+        Holiday = 8,
+        #[serde(rename = "CH_SUMMER")]
+        ChSummer = 9,
+        #[serde(rename = "CC_WINTER")]
+        CcWinter = 10,
+        #[serde(rename = "PRIO_STOP")]
+        PrioStop = 11,
+        #[serde(rename = "OFF")]
+        Off = 12,
+        #[serde(rename = "RELEASE_OFF")]
+        ReleaseOff = 13,
+        #[serde(rename = "TIME_OFF")]
+        TimeOff = 14,
+        #[serde(rename = "STBY")]
+        Stby = 15,
+        #[serde(rename = "STBY_HEATING")]
+        StbyHeating = 16,
+        #[serde(rename = "STBY_ECO")]
+        StbyEco = 17,
+        #[serde(rename = "STBY_COOLING")]
+        StbyCooling = 18,
+        #[serde(rename = "STBY_FROST")]
+        StbyFrost = 19,
+        #[serde(rename = "STBY_FLOOR_DRY")]
+        StbyFloordry = 20,
+    }
+}
+
+lambda_enum! {
+    pub enum SolarStateEnum {
+        #[serde(rename = "STBY")]
+        Stby = 0,
+        #[serde(rename = "HEATING")]
+        Heating = 1,
+        #[serde(rename = "ERROR")]
+        Error = 2,
+        #[serde(rename = "OFF")]
+        Off = 3,
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, EnumIter,Serialize, Deserialize, Display)]
-#[repr(u8)]
-pub enum HeatingCircuitState {
-    #[serde(rename = "HEATING")]
-    Heating = 0,
-    #[serde(rename = "ECO")]
-    Eco = 1,
-    #[serde(rename = "COOLING")]
-    Cooling = 2,
-    #[serde(rename = "FLOOR_DRY")]
-    Floordry = 3,
-    #[serde(rename = "FROST")]
-    Frost = 4,
-    #[serde(rename = "MAX_TEMP")]
-    MaxTemp = 5,
-    #[serde(rename = "ERROR")]
-    Error = 6,
-    #[serde(rename = "SERVICE")]
-    Service = 7,
-    #[serde(rename = "HOLIDAY")]
-    Holiday = 8,
-    #[serde(rename = "CH_SUMMER")]
-    ChSummer = 9,
-    #[serde(rename = "CC_WINTER")]
-    CcWinter = 10,
-    #[serde(rename = "PRIO_STOP")]
-    PrioStop = 11,
-    #[serde(rename = "OFF")]
-    Off = 12,
-    #[serde(rename = "RELEASE_OFF")]
-    ReleaseOff = 13,
-    #[serde(rename = "TIME_OFF")]
-    TimeOff = 14,
-    #[serde(rename = "STBY")]
-    Stby = 15,
-    #[serde(rename = "STBY_HEATING")]
-    StbyHeating = 16,
-    #[serde(rename = "STBY_ECO")]
-    StbyEco = 17,
-    #[serde(rename = "STBY_COOLING")]
-    StbyCooling = 18,
-    #[serde(rename = "STBY_FROST")]
-    StbyFrost = 19,
-    #[serde(rename = "STBY_FLOOR_DRY")]
-    StbyFloordry = 20,
-}
\ No newline at end of file
+lambda_enum! {
+    pub enum HeatingCircuitMode {
+        #[serde(rename = "OFF")]
+        Off = 0,
+        #[serde(rename = "MANUAL")]
+        Manual = 1,
+        #[serde(rename = "AUTOMATIK")]
+        Automatik = 2,
+        #[serde(rename = "AUTO_HEATING")]
+        AutoHeating = 3,
+        #[serde(rename = "AUTO_COOLING")]
+        AutoCooling = 4,
+        #[serde(rename = "FROST")]
+        Frost = 5,
+        #[serde(rename = "SUMMER")]
+        Summer = 6,
+        #[serde(rename = "FLOOR_DRY")]
+        FloorDry = 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_code_matches_sparse_codes() {
+        assert_eq!(HeatPumpStateEnum::from_code(0), HeatPumpStateEnum::Init);
+        assert_eq!(HeatPumpStateEnum::from_code(10), HeatPumpStateEnum::Defrosting);
+        assert_eq!(HeatPumpStateEnum::from_code(20), HeatPumpStateEnum::Stopping);
+        assert_eq!(HeatPumpStateEnum::from_code(31), HeatPumpStateEnum::AlarmBlock);
+        assert_eq!(HeatPumpStateEnum::from_code(40), HeatPumpStateEnum::ErrorReset);
+    }
+
+    #[test]
+    fn from_code_keeps_undocumented_codes() {
+        assert_eq!(HeatPumpStateEnum::from_code(11), HeatPumpStateEnum::Unknown(11));
+        assert_eq!(AmbientStateEnum::from_code(4), AmbientStateEnum::Unknown(4));
+        assert_eq!(HeatingCircuitMode::from_code(u16::MAX), HeatingCircuitMode::Unknown(u16::MAX));
+    }
+
+    #[test]
+    fn unknown_codes_are_displayed_and_serialized_with_their_code() {
+        let state = HeatPumpStateEnum::from_code(11);
+        assert_eq!(state.to_string(), "Unknown(11)");
+        assert_eq!(serde_json::to_string(&state).unwrap(), r#"{"UNKNOWN":11}"#);
+        assert_eq!(serde_json::to_string(&HeatPumpStateEnum::FaultLock).unwrap(), r#""FAULT_LOCK""#);
+    }
+}
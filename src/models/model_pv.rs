@@ -1,8 +1,13 @@
 use std::fmt::{Display, Error, Formatter};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// Power flows of the inverter in W, positive battery power charges the battery
+/// and positive grid power is drawn from the grid
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PvData {
+    pub event_timestamp: DateTime<Utc>,
+    pub clock_skew_ms: i64,
     pub battery_power:i64,
     pub battery_percentage:f64,
     pub grid:i64,
@@ -13,11 +18,12 @@ pub struct PvData {
 
 impl Display for crate::models::model_pv::PvData {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(),Error> {
-        writeln!(f, "Battery power : {}", self.battery_power)?;
-        writeln!(f, "Battery percentage : {}", self.battery_percentage)?;
-        writeln!(f, "Grid Power: {}", self.grid)?;
-        writeln!(f, "Home Power{}", self.home)?;
-        writeln!(f, "PV Power{}", self.pv)?;
-        writeln!(f, "Wallbox Power{}", self.wallbox)
+        writeln!(f, "Event Timestamp: {}", self.event_timestamp)?;
+        writeln!(f, "Battery Power: {} W", self.battery_power)?;
+        writeln!(f, "Battery Percentage: {} %", self.battery_percentage)?;
+        writeln!(f, "Grid Power: {} W", self.grid)?;
+        writeln!(f, "Home Power: {} W", self.home)?;
+        writeln!(f, "PV Power: {} W", self.pv)?;
+        writeln!(f, "Wallbox Power: {} W", self.wallbox)
     }
 }
@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Error, Formatter};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// All sensor readings stored under one event timestamp
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorSample {
+    pub event_timestamp: DateTime<Utc>,
+    pub clock_skew_ms: i64,
+    pub readings: Vec<SensorReading>,
+    /// State IDs or topics with a reading that no device could be found for
+    #[serde(skip)]
+    pub unmatched_devices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SensorReading {
+    /// Device ID from the device map
+    pub device: String,
+    /// Every numeric quantity of the payload allowed by the device map, e.g. `humidity` or `battery`
+    #[serde(default)]
+    pub quantities: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor: Option<String>,
+}
+
+impl Display for SensorReading {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(),Error> {
+        writeln!(f, "{}", self.device)?;
+        for (quantity, value) in &self.quantities {
+            writeln!(f, "{}: {}", quantity, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::device_map::DeviceMap;
+use crate::mapper::map_sensor_payload;
+use crate::models::model_sensor::SensorSample;
+use crate::postgres_client::PostgresClient;
+use chrono::{DateTime, Utc};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, Publish, QoS};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Subscribes to the sensor topics on the MQTT broker and stores
+/// every reading as it arrives instead of polling ioBroker for the latest value.
+pub struct MqttTemperatureClient {
+    options: MqttOptions,
+    topics: Vec<String>,
+    device_map: DeviceMap,
+    reconnect_delay: Duration,
+}
+
+impl MqttTemperatureClient {
+    pub fn new(
+        host: String,
+        port: u16,
+        client_id: String,
+        credentials: Option<(String, String)>,
+        topics: Vec<String>,
+        device_map: DeviceMap,
+    ) -> Self {
+        let mut options = MqttOptions::new(client_id, host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_clean_session(true);
+        if let Some((user, password)) = credentials {
+            options.set_credentials(user, password);
+        }
+
+        Self {
+            options,
+            topics,
+            device_map,
+            reconnect_delay: Duration::from_secs(5),
+        }
+    }
+
+    pub async fn run(self, database_client: PostgresClient) {
+        let (client, mut eventloop) = AsyncClient::new(self.options.clone(), 100);
+
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // Subscriptions are lost with a clean session, so renew them on every (re)connect
+                    println!("Connected to MQTT broker, subscribing to {:?}", self.topics);
+                    for topic in &self.topics {
+                        if let Err(e) = client.subscribe(topic.clone(), QoS::AtLeastOnce).await {
+                            eprintln!("Error subscribing to {}: {}", topic, e);
+                        }
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    if let Err(e) = handle_publish(&publish, &self.device_map, &database_client).await {
+                        eprintln!("Error handling MQTT message on {}: {}", publish.topic, e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("MQTT connection error: {}, reconnecting in {:?}", e, self.reconnect_delay);
+                    sleep(self.reconnect_delay).await;
+                }
+            }
+        }
+    }
+}
+
+async fn handle_publish(
+    publish: &Publish,
+    device_map: &DeviceMap,
+    database_client: &PostgresClient,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sample = publish_to_sample(publish, device_map, Utc::now())?;
+    if let Some(sample) = sample {
+        database_client.write_temperature_data(sample).await?;
+    }
+    Ok(())
+}
+
+// The sample a message is stored as, `None` for messages that are not stored.
+// MQTT messages carry no source timestamp, so they are stored under `received_at`.
+fn publish_to_sample(
+    publish: &Publish,
+    device_map: &DeviceMap,
+    received_at: DateTime<Utc>,
+) -> Result<Option<SensorSample>, Box<dyn std::error::Error>> {
+    // Retained messages are replayed on every subscribe and carry no timestamp,
+    // storing them would duplicate old readings under the current time
+    if publish.retain {
+        println!("Skipping retained message on {}", publish.topic);
+        return Ok(None);
+    }
+
+    let payload = std::str::from_utf8(&publish.payload)?;
+    let Some(reading) = map_sensor_payload(device_map, &publish.topic, payload)? else {
+        println!("No sensor quantity in message on {}", publish.topic);
+        return Ok(None);
+    };
+    println!("Sensor data received from {}: {:?}", publish.topic, reading.quantities);
+    Ok(Some(SensorSample {
+        event_timestamp: received_at,
+        clock_skew_ms: 0,
+        readings: vec![reading],
+        unmatched_devices: Vec::new(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &str = r#"
+pattern = "^adfhome/Temperatur_(?P<device>[^/]+)$"
+quantities = ["temperature", "humidity", "battery"]
+
+[devices]
+Bad = { name = "Bathroom sensor", room = "Bathroom", floor = "First floor" }
+"#;
+
+    fn publish(topic: &str, payload: impl Into<Vec<u8>>, retain: bool) -> Publish {
+        let mut publish = Publish::new(topic, QoS::AtLeastOnce, payload);
+        publish.retain = retain;
+        publish
+    }
+
+    #[test]
+    fn publish_to_sample_maps_the_accepted_quantities() {
+        let device_map = DeviceMap::from_toml(MAP).unwrap();
+        let received_at = Utc::now();
+        let message = publish(
+            "adfhome/Temperatur_Bad",
+            r#"{"temperature":21.5,"humidity":55,"battery":100,"linkquality":120,"state":"ON"}"#,
+            false,
+        );
+        let sample = publish_to_sample(&message, &device_map, received_at).unwrap().unwrap();
+
+        assert_eq!((sample.event_timestamp, sample.clock_skew_ms), (received_at, 0));
+        assert_eq!(sample.readings.len(), 1);
+        let reading = &sample.readings[0];
+        assert_eq!(reading.device, "Bad");
+        assert_eq!(reading.room.as_deref(), Some("Bathroom"));
+        let quantities: Vec<(&str, f64)> = reading.quantities.iter().map(|(quantity, value)| (quantity.as_str(), *value)).collect();
+        assert_eq!(quantities, [("battery", 100.0), ("humidity", 55.0), ("temperature", 21.5)]);
+    }
+
+    #[test]
+    fn publish_to_sample_skips_retained_messages() {
+        let device_map = DeviceMap::from_toml(MAP).unwrap();
+        let message = publish("adfhome/Temperatur_Bad", r#"{"temperature":21.5}"#, true);
+        assert!(publish_to_sample(&message, &device_map, Utc::now()).unwrap().is_none());
+    }
+
+    #[test]
+    fn publish_to_sample_skips_messages_without_quantities() {
+        let device_map = DeviceMap::from_toml(MAP).unwrap();
+        for payload in [r#"{"linkquality":120}"#, "ON", "21.5", r#"{"temperature":"warm"}"#] {
+            let message = publish("adfhome/Temperatur_Bad", payload, false);
+            assert!(publish_to_sample(&message, &device_map, Utc::now()).unwrap().is_none(), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn publish_to_sample_rejects_unknown_devices_and_invalid_payloads() {
+        let device_map = DeviceMap::from_toml(MAP).unwrap();
+        let message = publish("zigbee2mqtt/kitchen", r#"{"temperature":21.5}"#, false);
+        assert!(publish_to_sample(&message, &device_map, Utc::now()).is_err());
+        let message = publish("adfhome/Temperatur_Bad", vec![0xFF, 0xFE], false);
+        assert!(publish_to_sample(&message, &device_map, Utc::now()).is_err());
+    }
+}
@@ -1,11 +1,39 @@
 use std::error::Error;
-use sea_orm::{ActiveModelTrait, ConnectOptions, Database, DatabaseConnection};
-use crate::{mapper::{ToLambdaDataModel, ToTemperatureDataModel}, models::{model_lambda::LambdaData, model_temperature::TemperatureData}};
+use std::sync::Arc;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use sea_orm_migration::MigratorTrait;
+use sea_orm::{sea_query::OnConflict, ActiveModelTrait, ConnectOptions, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, DbErr, EntityTrait, FromQueryResult, IntoActiveModel, RuntimeErr, SqlxError, Statement, TransactionTrait};
+use tokio::sync::Mutex;
+use crate::{entity::{charging_session, heatpump, pv_data, sensor_device}, mapper::{ToChargingSessionModel, ToLambdaDataModel, ToModuleModel, ToPvDataModel, ToSensorReadingModels}, migrator::Migrator, models::{model_charging::ChargingSession, model_lambda::LambdaData, model_pv::PvData, model_sensor::SensorSample}, retention::{self, RetentionPolicy}, rollup, spool::{Spool, SpooledWrite}, timescale};
 
+/// Time range without stored samples, bounded by the samples around it
+#[derive(Debug, FromQueryResult)]
+pub struct Gap {
+    pub gap_start: DateTime<FixedOffset>,
+    pub gap_end: DateTime<FixedOffset>,
+}
+
+/// Charging sessions of one month, a row of the `charging_session_monthly` view
+#[derive(Debug, FromQueryResult)]
+pub struct ChargingMonth {
+    pub month: DateTime<FixedOffset>,
+    pub sessions: i64,
+    pub energy_kwh: f64,
+    pub pv_energy_kwh: f64,
+    pub battery_energy_kwh: f64,
+    pub grid_energy_kwh: f64,
+    pub pv_share: Option<f64>,
+}
 
+#[derive(Clone)]
 pub struct PostgresClient {
 
-    db:DatabaseConnection
+    // Not Clone itself once sea-orm's mock feature is enabled for the tests
+    db: Arc<DatabaseConnection>,
+    // Shared with the clones handed to the MQTT task, so spooled writes stay in order
+    spool: Option<Arc<Mutex<Spool>>>,
+    // Whether TimescaleDB is to be set up, while the startup migrations wait for the database
+    pending_migration: Arc<Mutex<Option<bool>>>,
 }
 
 impl PostgresClient {
@@ -16,28 +44,337 @@ impl PostgresClient {
         database_name: String,
         port: u16,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let config = ConnectOptions::new(format!(
+        let mut config = ConnectOptions::new(format!(
             "postgres://{}:{}@{}:{}/{}",
             user, password, hostname, port, database_name
         ));
-        let db = Database::connect(config.clone()).await.unwrap();
-        Ok(PostgresClient { db })
+        // Fail fast while the database is down, the write is spooled instead of blocking the intervals
+        config.acquire_timeout(std::time::Duration::from_secs(5));
+        // Connections are opened on first use, so a database that is down at startup only delays the writes
+        config.connect_lazy(true);
+        let db = Database::connect(config).await?;
+        Ok(PostgresClient {
+            db: Arc::new(db),
+            spool: None,
+            pending_migration: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Keeps writes that fail while the database is unreachable in `spool` and replays them later
+    pub fn with_spool(mut self, spool: Spool) -> Self {
+        if spool.depth() > 0 {
+            println!("Spool holds {} samples from a previous run", spool.depth());
+        }
+        self.spool = Some(Arc::new(Mutex::new(spool)));
+        self
+    }
+
+    /// Applies `steps` pending migrations, all of them when `None`, and sets up TimescaleDB when asked.
+    /// Returns whether TimescaleDB is in use.
+    pub async fn migrate_up(&self, steps: Option<u32>, timescale: bool) -> Result<bool, Box<dyn Error>> {
+        Ok(self.apply_migrations(steps, timescale).await?)
+    }
+
+    /// Applies all pending migrations like `migrate_up`. When the database is unreachable they are
+    /// applied before the first write that reaches it, TimescaleDB is then assumed to be in use as asked.
+    pub async fn migrate_on_startup(&self, timescale: bool) -> Result<bool, Box<dyn Error>> {
+        match self.apply_migrations(None, timescale).await {
+            Err(e) if is_unavailable(&e) => {
+                eprintln!("Database unavailable, migrations are applied once it is reachable: {}", e);
+                *self.pending_migration.lock().await = Some(timescale);
+                Ok(timescale)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Rolls back `steps` applied migrations, all of them when `None`
+    pub async fn migrate_down(&self, steps: Option<u32>) -> Result<(), Box<dyn Error>> {
+        Ok(Migrator::down(&*self.db, steps).await?)
+    }
+
+
+    /// Brings the heat pump and sensor rollup tables up to date, returns the number of buckets written
+    pub async fn refresh_rollups(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(rollup::refresh(&*self.db).await?)
+    }
+
+    /// Deletes the raw rows older than the policy allows, returns the number of rows deleted
+    pub async fn apply_retention(&self, policy: &RetentionPolicy, batch_size: u64) -> Result<u64, Box<dyn Error>> {
+        Ok(retention::apply(&*self.db, policy, batch_size).await?)
+    }
+
+    /// Name and status (`Applied` or `Pending`) of every migration
+    pub async fn migration_status(&self) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let migrations = Migrator::get_migration_with_status(&*self.db).await?;
+        Ok(migrations
+            .iter()
+            .map(|migration| (migration.name().to_string(), migration.status().to_string()))
+            .collect())
     }
 
     pub async fn write_lambda_data(&self, data: LambdaData) -> Result<i64, Box<dyn Error>> {
+        self.store(SpooledWrite::Lambda(Box::new(data))).await
+    }
+
+    pub async fn write_temperature_data(&self, data: SensorSample) -> Result<i64, Box<dyn Error>> {
+        self.store(SpooledWrite::Temperature(data)).await
+    }
+
+    pub async fn write_pv_data(&self, data: PvData) -> Result<i64, Box<dyn Error>> {
+        self.store(SpooledWrite::Pv(data)).await
+    }
+
+    pub async fn write_charging_session(&self, session: ChargingSession) -> Result<i64, Box<dyn Error>> {
+        self.store(SpooledWrite::ChargingSession(session)).await
+    }
+
+    // Writes directly when nothing is spooled, otherwise after the spooled writes so the order is kept
+    async fn store(&self, write: SpooledWrite) -> Result<i64, Box<dyn Error>> {
+        let Some(spool) = &self.spool else {
+            self.apply_pending_migration().await?;
+            return Ok(self.insert(write).await?);
+        };
+        let mut spool = spool.lock().await;
+        let mut write_now = match self.apply_pending_migration().await {
+            Ok(()) => true,
+            Err(e) if is_unavailable(&e) => false,
+            Err(e) => {
+                eprintln!("Error applying migrations, spooling sample: {}", e);
+                false
+            }
+        };
+        if write_now && spool.depth() > 0 {
+            // A spool that cannot be read must not cost the current sample, it is written out of order
+            if let Err(e) = self.replay(&mut spool).await {
+                eprintln!("Error replaying spool: {}", e);
+            } else {
+                write_now = spool.depth() == 0;
+            }
+        }
+        if write_now {
+            match self.insert(write.clone()).await {
+                Err(e) if is_unavailable(&e) => eprintln!("Database unavailable, spooling sample: {}", e),
+                result => return Ok(result?),
+            }
+        }
+        spool.append(&write)?;
+        println!("Spool holds {} samples ({} bytes)", spool.depth(), spool.size());
+        Ok(0)
+    }
+
+    // Writes the spooled samples oldest first until the database fails again
+    async fn replay(&self, spool: &mut Spool) -> Result<(), Box<dyn Error>> {
+        let entries = spool.entries()?;
+        let mut replayed = 0;
+        for (index, entry) in entries.iter().enumerate() {
+            let write: SpooledWrite = match serde_json::from_str(entry) {
+                Ok(write) => write,
+                Err(e) => {
+                    eprintln!("Dropping unreadable spool entry: {}", e);
+                    continue;
+                }
+            };
+            match self.insert(write).await {
+                Ok(_) => replayed += 1,
+                Err(e) if is_unavailable(&e) => {
+                    spool.retain(&entries[index..])?;
+                    println!("Database still unavailable, spool holds {} samples", spool.depth());
+                    return Ok(());
+                }
+                // Retrying would block the spool forever
+                Err(e) => eprintln!("Dropping spooled sample refused by the database: {}", e),
+            }
+        }
+        spool.retain(&[])?;
+        println!("Replayed {} spooled samples", replayed);
+        Ok(())
+    }
+
+    async fn apply_migrations(&self, steps: Option<u32>, timescale: bool) -> Result<bool, DbErr> {
+        Migrator::up(&*self.db, steps).await?;
+        if !timescale {
+            return Ok(false);
+        }
+        let enabled = timescale::enable(&*self.db).await?;
+        match enabled {
+            true => println!("TimescaleDB hypertables and continuous aggregates are set up"),
+            false => println!("TimescaleDB is not available, the tables stay plain PostgreSQL tables"),
+        }
+        Ok(enabled)
+    }
+
+    // Applies the migrations that had to wait at startup, before anything is written
+    async fn apply_pending_migration(&self) -> Result<(), DbErr> {
+        let mut pending = self.pending_migration.lock().await;
+        if let Some(timescale) = *pending {
+            self.apply_migrations(None, timescale).await?;
+            println!("Migrations applied now that the database is reachable");
+            *pending = None;
+        }
+        Ok(())
+    }
+
+    async fn insert(&self, write: SpooledWrite) -> Result<i64, DbErr> {
+        match write {
+            SpooledWrite::Lambda(data) => self.insert_lambda_data(*data).await,
+            SpooledWrite::Temperature(data) => self.insert_temperature_data(data).await,
+            SpooledWrite::Pv(data) => self.insert_pv_data(data).await,
+            SpooledWrite::ChargingSession(session) => self.insert_charging_session(session).await,
+        }
+    }
+
+    async fn insert_lambda_data(&self, data: LambdaData) -> Result<i64, DbErr> {
         println!("Writing data to database");
+        let event_timestamp = data.event_timestamp;
+        let heat_pumps = data.heat_pumps.iter().map(|module| module.to_module_model(event_timestamp)).collect();
+        let boilers = data.boilers.iter().map(|module| module.to_module_model(event_timestamp)).collect();
+        let buffers = data.buffers.iter().map(|module| module.to_module_model(event_timestamp)).collect();
+        let solar_modules = data.solar_modules.iter().map(|module| module.to_module_model(event_timestamp)).collect();
+        let heating_circuits = data.heating_circuits.iter().map(|module| module.to_module_model(event_timestamp)).collect();
         let model = data.to_lambda_data();
-        model.insert(&self.db).await?;
-        println!("Data written to database");
-        return Ok(1);
+
+        let txn = self.db.begin().await?;
+        // Source timestamps repeat when ioBroker did not update the states since the last fetch
+        let rows = heatpump::Entity::insert(model)
+            .on_conflict(OnConflict::column(heatpump::Column::EventTimestamp).do_nothing().to_owned())
+            .exec_without_returning(&txn)
+            .await?;
+        insert_modules(&txn, heat_pumps).await?;
+        insert_modules(&txn, boilers).await?;
+        insert_modules(&txn, buffers).await?;
+        insert_modules(&txn, solar_modules).await?;
+        insert_modules(&txn, heating_circuits).await?;
+        txn.commit().await?;
+        if rows == 0 {
+            println!("Data for this timestamp already in database");
+        } else {
+            println!("Data written to database");
+        }
+        Ok(rows as i64)
     }
 
-    pub async fn write_temperature_data(&self, data: Vec<TemperatureData>) -> Result<i64, Box<dyn Error>> {
+    async fn insert_temperature_data(&self, data: SensorSample) -> Result<i64, DbErr> {
         println!("Writing temperature data to database");
-        let model = data.to_temperature_data();
-        model.insert(&self.db).await?;
-        println!("Temperature data written to database");
-        return Ok(1);
+        let devices = data.to_sensor_devices();
+        let readings = data.to_sensor_readings();
+
+        let txn = self.db.begin().await?;
+        if !devices.is_empty() {
+            // Name, room and floor follow the device map
+            sensor_device::Entity::insert_many(devices)
+                .on_conflict(
+                    OnConflict::column(sensor_device::Column::Id)
+                        .update_columns([sensor_device::Column::Name, sensor_device::Column::Room, sensor_device::Column::Floor])
+                        .to_owned(),
+                )
+                .exec_without_returning(&txn)
+                .await?;
+        }
+        // MQTT messages of different devices can share a timestamp, so readings are checked on their own
+        let rows = insert_modules(&txn, readings).await?;
+        txn.commit().await?;
+        if rows == 0 {
+            println!("Temperature data for this timestamp already in database");
+        } else {
+            println!("Temperature data written to database");
+        }
+        Ok(rows as i64)
+    }
+
+    async fn insert_pv_data(&self, data: PvData) -> Result<i64, DbErr> {
+        println!("Writing PV data to database");
+        let model = data.to_pv_data();
+        let rows = pv_data::Entity::insert(model)
+            .on_conflict(OnConflict::column(pv_data::Column::EventTimestamp).do_nothing().to_owned())
+            .exec_without_returning(&*self.db)
+            .await?;
+        if rows == 0 {
+            println!("PV data for this timestamp already in database");
+        } else {
+            println!("PV data written to database");
+        }
+        Ok(rows as i64)
+    }
+
+    async fn insert_charging_session(&self, session: ChargingSession) -> Result<i64, DbErr> {
+        println!("Writing charging session to database");
+        let model = session.to_charging_session();
+        let rows = charging_session::Entity::insert(model)
+            .on_conflict(OnConflict::column(charging_session::Column::StartedAt).do_nothing().to_owned())
+            .exec_without_returning(&*self.db)
+            .await?;
+        if rows == 0 {
+            println!("Charging session already in database");
+        } else {
+            println!("Charging session written to database");
+        }
+        Ok(rows as i64)
+    }
+
+    pub async fn charging_months(&self) -> Result<Vec<ChargingMonth>, Box<dyn Error>> {
+        let statement = Statement::from_string(
+            DatabaseBackend::Postgres,
+            "SELECT * FROM charging_session_monthly ORDER BY month",
+        );
+        Ok(ChargingMonth::find_by_statement(statement).all(&*self.db).await?)
+    }
+
+    /// Finds ranges between `from` and `to` where consecutive rows of `table` are further apart
+    /// than `min_gap`, the range bounds count as rows so empty stretches at either end are found too
+    pub async fn find_gaps(
+        &self,
+        table: &'static str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        min_gap: Duration,
+    ) -> Result<Vec<Gap>, Box<dyn Error>> {
+        let sql = format!(
+            r#"SELECT gap_start, gap_end FROM (
+                 SELECT LAG(ts) OVER (ORDER BY ts) AS gap_start, ts AS gap_end FROM (
+                   SELECT event_timestamp AS ts FROM {table} WHERE event_timestamp BETWEEN $1 AND $2
+                   UNION ALL SELECT $1
+                   UNION ALL SELECT $2
+                 ) bounded
+               ) gaps
+               WHERE gap_end - gap_start > make_interval(secs => $3)
+               ORDER BY gap_start"#
+        );
+        let statement = Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            sql,
+            [from.into(), to.into(), (min_gap.num_milliseconds() as f64 / 1000.0).into()],
+        );
+        Ok(Gap::find_by_statement(statement).all(&*self.db).await?)
+    }
+}
+
+// Whether a write failed because the database could not be reached rather than because of the data,
+// including errors while PostgreSQL is starting up or shutting down (SQLSTATE classes 08 and 57)
+fn is_unavailable(e: &DbErr) -> bool {
+    match e {
+        DbErr::ConnectionAcquire(_) | DbErr::Conn(_) => true,
+        DbErr::Exec(RuntimeErr::SqlxError(e)) | DbErr::Query(RuntimeErr::SqlxError(e)) => match e {
+            SqlxError::Database(e) => e.code().is_some_and(|code| code.starts_with("08") || code.starts_with("57")),
+            SqlxError::Io(_) | SqlxError::Tls(_) | SqlxError::Protocol(_) | SqlxError::PoolTimedOut | SqlxError::PoolClosed | SqlxError::WorkerCrashed => true,
+            _ => false,
+        },
+        _ => false,
     }
 }
 
+// Inserts the rows of one module or reading table, skipping rows already stored for the timestamp
+async fn insert_modules<A, C>(db: &C, models: Vec<A>) -> Result<u64, DbErr>
+where
+    A: ActiveModelTrait + Send,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+    C: ConnectionTrait,
+{
+    if models.is_empty() {
+        return Ok(0);
+    }
+    A::Entity::insert_many(models)
+        .on_conflict(OnConflict::new().do_nothing().to_owned())
+        .exec_without_returning(db)
+        .await
+}
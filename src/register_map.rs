@@ -0,0 +1,790 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+/// Mapping shipped with the binary, used when no LAMBDA_REGISTER_MAP is configured
+const BUNDLED_MAP: &str = include_str!("../register_map.toml");
+
+/// Register distance between two instances of the same module
+pub const INSTANCE_REGISTER_STEP: u16 = 100;
+
+/// ioBroker's modbus adapter numbers holding registers from 40001, so "40002_Ambient_State"
+/// is register address 1 and "41001_HP1_Error_State" address 1000 on the Lambda controller.
+pub const HOLDING_REGISTER_OFFSET: u16 = 40001;
+
+#[derive(Debug)]
+pub enum RegisterMapError {
+    ReadError(String, std::io::Error),
+    ParseError(toml::de::Error),
+    InvalidMap(Vec<String>),
+}
+
+impl fmt::Display for RegisterMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegisterMapError::ReadError(path, e) => write!(f, "Error reading register map {}: {}", path, e),
+            RegisterMapError::ParseError(e) => write!(f, "Error parsing register map: {}", e),
+            RegisterMapError::InvalidMap(problems) => write!(f, "Invalid register map: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for RegisterMapError {}
+
+/// How the value of a state is decoded
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    Float,
+    Integer,
+    /// On/off state, stored as 0 or 1 in the register
+    Boolean,
+    /// Numeric code decoded into one of the enums in `model_lambda`
+    Enum,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Float => write!(f, "float"),
+            ValueType::Integer => write!(f, "integer"),
+            ValueType::Boolean => write!(f, "boolean"),
+            ValueType::Enum => write!(f, "enum"),
+        }
+    }
+}
+
+/// Unit of a value, shown in reports and exported with the sample
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Unit {
+    #[serde(rename = "°C")]
+    Celsius,
+    #[serde(rename = "K")]
+    Kelvin,
+    #[serde(rename = "%")]
+    Percent,
+    #[serde(rename = "W")]
+    Watt,
+    #[serde(rename = "kW")]
+    Kilowatt,
+    #[serde(rename = "Wh")]
+    WattHour,
+    #[serde(rename = "kWh")]
+    KilowattHour,
+    #[serde(rename = "l/min")]
+    LitersPerMinute,
+    #[serde(rename = "l/h")]
+    LitersPerHour,
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unit::Celsius => write!(f, "°C"),
+            Unit::Kelvin => write!(f, "K"),
+            Unit::Percent => write!(f, "%"),
+            Unit::Watt => write!(f, "W"),
+            Unit::Kilowatt => write!(f, "kW"),
+            Unit::WattHour => write!(f, "Wh"),
+            Unit::KilowattHour => write!(f, "kWh"),
+            Unit::LitersPerMinute => write!(f, "l/min"),
+            Unit::LitersPerHour => write!(f, "l/h"),
+        }
+    }
+}
+
+/// Order of the two registers of a 32-bit value
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WordOrder {
+    /// High word in the first register, as the Lambda controller sends it
+    #[default]
+    HighFirst,
+    LowFirst,
+}
+
+/// How a field is stored in the Lambda holding registers
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegisterDescription {
+    /// Holding register of this instance, fields without one can only be read through ioBroker
+    pub register: Option<u16>,
+    /// Factor from the raw register value to `unit`, e.g. 0.1 for a temperature in 0.1 °C steps
+    pub scale: f64,
+    pub signed: bool,
+    /// Number of registers, 1 for 16-bit and 2 for 32-bit values
+    pub words: u16,
+    pub word_order: WordOrder,
+    pub unit: Option<Unit>,
+}
+
+impl RegisterDescription {
+    /// Raw value of the field, `words` starts at its first register
+    pub fn decode(&self, words: &[u16]) -> i64 {
+        if self.words == 1 {
+            return if self.signed { words[0] as i16 as i64 } else { words[0] as i64 };
+        }
+        let (high, low) = match self.word_order {
+            WordOrder::HighFirst => (words[0], words[1]),
+            WordOrder::LowFirst => (words[1], words[0]),
+        };
+        let value = ((high as u32) << 16) | low as u32;
+        if self.signed { value as i32 as i64 } else { value as i64 }
+    }
+
+    /// Raw value in `unit`, with as many decimals as the scale has
+    pub fn scaled(&self, raw: i64) -> String {
+        if self.scale == 1.0 {
+            return raw.to_string();
+        }
+        let decimals = (-self.scale.log10()).ceil().max(0.0) as usize;
+        format!("{:.*}", decimals, raw as f64 * self.scale)
+    }
+}
+
+/// Lambda modules that can be cascaded, each instance has its own block of registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleKind {
+    HeatPump,
+    Boiler,
+    Buffer,
+    Solar,
+    HeatingCircuit,
+}
+
+impl ModuleKind {
+    pub const ALL: [ModuleKind; 5] = [
+        ModuleKind::HeatPump,
+        ModuleKind::Boiler,
+        ModuleKind::Buffer,
+        ModuleKind::Solar,
+        ModuleKind::HeatingCircuit,
+    ];
+
+    /// Section of the module in the register map file
+    pub fn section(&self) -> &'static str {
+        match self {
+            ModuleKind::HeatPump => "heatpump",
+            ModuleKind::Boiler => "boiler",
+            ModuleKind::Buffer => "buffer",
+            ModuleKind::Solar => "solar",
+            ModuleKind::HeatingCircuit => "heating_circuit",
+        }
+    }
+
+    /// Most instances the Lambda controller supports
+    pub fn max_instances(&self) -> usize {
+        match self {
+            ModuleKind::HeatPump => 3,
+            ModuleKind::Boiler => 5,
+            ModuleKind::Buffer => 5,
+            ModuleKind::Solar => 2,
+            ModuleKind::HeatingCircuit => 12,
+        }
+    }
+
+    // Every field of the module read by the mapper
+    fn fields(&self) -> &'static [FieldSpec] {
+        match self {
+            ModuleKind::HeatPump => HEATPUMP_FIELDS,
+            ModuleKind::Boiler => BOILER_FIELDS,
+            ModuleKind::Buffer => BUFFER_FIELDS,
+            ModuleKind::Solar => SOLAR_FIELDS,
+            ModuleKind::HeatingCircuit => HEATING_CIRCUIT_FIELDS,
+        }
+    }
+}
+
+impl fmt::Display for ModuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.section())
+    }
+}
+
+/// A field read by the mapper together with the type its state has to decode to
+struct FieldSpec {
+    name: &'static str,
+    value_type: ValueType,
+    /// Added after the original register set, adapters set up for that set do not have the state,
+    /// so a missing state leaves the field empty in every mapping mode
+    optional: bool,
+}
+
+const fn required(name: &'static str, value_type: ValueType) -> FieldSpec {
+    FieldSpec { name, value_type, optional: false }
+}
+
+const fn optional(name: &'static str, value_type: ValueType) -> FieldSpec {
+    FieldSpec { name, value_type, optional: true }
+}
+
+// Ambient and E-Manager fields, which exist once
+const GENERAL_FIELDS: &[FieldSpec] = &[
+    optional("ambient_error_number", ValueType::Integer),
+    required("ambient_state", ValueType::Enum),
+    optional("ambient_temperature_1h", ValueType::Float),
+    optional("ambient_temperature_24h", ValueType::Float),
+    required("ambient_temperature_calculated", ValueType::Float),
+    optional("emanager_error_number", ValueType::Integer),
+    required("emanager_operating_state", ValueType::Enum),
+    required("emanager_pv_power", ValueType::Float),
+    required("emanager_actual_power", ValueType::Float),
+    required("emanager_power_setpoint", ValueType::Float),
+];
+
+const HEATPUMP_FIELDS: &[FieldSpec] = &[
+    required("error_state", ValueType::Enum),
+    required("error_number", ValueType::Integer),
+    required("state", ValueType::Enum),
+    required("operating_state", ValueType::Enum),
+    required("flowline_temp", ValueType::Float),
+    required("return_line_temp", ValueType::Float),
+    required("volume_sink", ValueType::Float),
+    required("energy_source_inlet_temp", ValueType::Float),
+    optional("energy_source_outlet_temp", ValueType::Float),
+    required("volume_source_flow", ValueType::Float),
+    required("compressor_rating", ValueType::Float),
+    required("actual_heating_capacity", ValueType::Float),
+    required("inverter_actual_power", ValueType::Float),
+    required("current_cop", ValueType::Float),
+    required("request_type", ValueType::Enum),
+    required("request_flow_temp", ValueType::Float),
+    required("request_return_temp", ValueType::Float),
+    required("request_temp_diff", ValueType::Float),
+    optional("second_stage_active", ValueType::Boolean),
+    required("electric_energy", ValueType::Float),
+    required("heat_energy", ValueType::Float),
+];
+
+const BOILER_FIELDS: &[FieldSpec] = &[
+    optional("error_number", ValueType::Integer),
+    required("state", ValueType::Enum),
+    required("high_temp", ValueType::Float),
+    required("low_temp", ValueType::Float),
+    optional("circulation_temp", ValueType::Float),
+    optional("circulation_pump_active", ValueType::Boolean),
+    required("max_temp", ValueType::Float),
+];
+
+const BUFFER_FIELDS: &[FieldSpec] = &[
+    optional("error_number", ValueType::Integer),
+    required("state", ValueType::Enum),
+    required("high_temp", ValueType::Float),
+    required("low_temp", ValueType::Float),
+    optional("request_type", ValueType::Enum),
+    optional("request_flow_temp", ValueType::Float),
+    optional("request_return_temp", ValueType::Float),
+    optional("request_temp_diff", ValueType::Float),
+    optional("request_capacity", ValueType::Float),
+    required("max_temp", ValueType::Float),
+];
+
+const SOLAR_FIELDS: &[FieldSpec] = &[
+    required("error_number", ValueType::Integer),
+    required("state", ValueType::Enum),
+    required("collector_temp", ValueType::Float),
+    required("buffer_1_temp", ValueType::Float),
+    required("buffer_2_temp", ValueType::Float),
+    required("max_buffer_temp", ValueType::Float),
+    required("buffer_changeover_temp", ValueType::Float),
+];
+
+const HEATING_CIRCUIT_FIELDS: &[FieldSpec] = &[
+    optional("error_number", ValueType::Integer),
+    required("state", ValueType::Enum),
+    required("flow_temp", ValueType::Float),
+    optional("return_temp", ValueType::Float),
+    optional("room_temp", ValueType::Float),
+    optional("flow_setpoint", ValueType::Float),
+    optional("operating_mode", ValueType::Enum),
+    optional("flow_offset", ValueType::Float),
+    optional("room_heating_setpoint", ValueType::Float),
+    optional("room_cooling_setpoint", ValueType::Float),
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegisterMapFile {
+    #[serde(default)]
+    prefix: String,
+    general: BTreeMap<String, FieldEntry>,
+    heatpump: ModuleEntry,
+    boiler: ModuleEntry,
+    buffer: ModuleEntry,
+    solar: ModuleEntry,
+    heating_circuit: ModuleEntry,
+}
+
+impl RegisterMapFile {
+    fn module(&self, kind: ModuleKind) -> &ModuleEntry {
+        match kind {
+            ModuleKind::HeatPump => &self.heatpump,
+            ModuleKind::Boiler => &self.boiler,
+            ModuleKind::Buffer => &self.buffer,
+            ModuleKind::Solar => &self.solar,
+            ModuleKind::HeatingCircuit => &self.heating_circuit,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModuleEntry {
+    instances: usize,
+    fields: BTreeMap<String, FieldEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldEntry {
+    /// Register of the first instance, needed for `{register}` in module states
+    register: Option<u16>,
+    state: String,
+    #[serde(rename = "type")]
+    value_type: ValueType,
+    /// Factor applied to raw register values, 1 if omitted
+    scale: Option<f64>,
+    /// Float and integer fields are signed unless set otherwise
+    signed: Option<bool>,
+    words: Option<u16>,
+    word_order: Option<WordOrder>,
+    unit: Option<Unit>,
+}
+
+impl FieldEntry {
+    fn description(&self, register: Option<u16>) -> RegisterDescription {
+        RegisterDescription {
+            register,
+            scale: self.scale.unwrap_or(1.0),
+            signed: self
+                .signed
+                .unwrap_or(matches!(self.value_type, ValueType::Float | ValueType::Integer)),
+            words: self.words.unwrap_or(1),
+            word_order: self.word_order.unwrap_or_default(),
+            unit: self.unit,
+        }
+    }
+}
+
+// States of one module instance by field
+type InstanceStates = HashMap<&'static str, String>;
+
+/// Validated mapping from `LambdaData` fields to the ioBroker states they are read from
+#[derive(Debug, Clone)]
+pub struct RegisterMap {
+    prefix: String,
+    general: HashMap<&'static str, String>,
+    modules: HashMap<ModuleKind, Vec<InstanceStates>>,
+    descriptions: HashMap<String, RegisterDescription>,
+    optional: HashSet<String>,
+}
+
+impl RegisterMap {
+    /// The mapping bundled with the binary, matching the ioBroker modbus adapter defaults
+    pub fn bundled() -> Result<Self, RegisterMapError> {
+        Self::from_toml(BUNDLED_MAP)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, RegisterMapError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| RegisterMapError::ReadError(path.display().to_string(), e))?;
+        Self::from_toml(&content)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, RegisterMapError> {
+        let file: RegisterMapFile = toml::from_str(content).map_err(RegisterMapError::ParseError)?;
+
+        // Collect every problem so a broken file can be fixed in one go
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+        let mut check_unique = |state: &String, problems: &mut Vec<String>| {
+            if !seen.insert(state.clone()) {
+                problems.push(format!("state {} is mapped more than once", state));
+            }
+        };
+
+        let mut descriptions = HashMap::new();
+        let mut optional = HashSet::new();
+        let mut general = HashMap::new();
+        let general_fields = checked_fields("general", &file.general, GENERAL_FIELDS, &mut problems);
+        check_register_range("general", &general_fields, 1, &mut problems);
+        for (spec, entry) in general_fields {
+            let state = format!("{}{}", file.prefix, entry.state);
+            check_unique(&state, &mut problems);
+            descriptions.insert(state.clone(), entry.description(entry.register));
+            if spec.optional {
+                optional.insert(state.clone());
+            }
+            general.insert(spec.name, state);
+        }
+
+        let mut modules = HashMap::new();
+        for kind in ModuleKind::ALL {
+            let module = file.module(kind);
+            if module.instances == 0 || module.instances > kind.max_instances() {
+                problems.push(format!(
+                    "{} instances must be between 1 and {}",
+                    kind,
+                    kind.max_instances()
+                ));
+            }
+
+            let fields = checked_fields(kind.section(), &module.fields, kind.fields(), &mut problems);
+            check_register_range(kind.section(), &fields, module.instances.min(kind.max_instances()), &mut problems);
+            let mut instances = Vec::new();
+            for instance in 0..module.instances.min(kind.max_instances()) {
+                let mut states = InstanceStates::new();
+                for (spec, entry) in &fields {
+                    let register = match (entry.register, entry.state.contains("{register}")) {
+                        (Some(register), _) => match instance_register(register, instance, 1) {
+                            Some(register) => register,
+                            // Reported by check_register_range
+                            None => continue,
+                        },
+                        (None, false) => 0,
+                        (None, true) => {
+                            if instance == 0 {
+                                problems.push(format!("{}.{} uses {{register}} without a register", kind, spec.name));
+                            }
+                            continue;
+                        }
+                    };
+                    let state = entry
+                        .state
+                        .replace("{n}", &(instance + 1).to_string())
+                        .replace("{register}", &register.to_string());
+                    let state = format!("{}{}", file.prefix, state);
+                    check_unique(&state, &mut problems);
+                    let instance_register = entry.register.map(|_| register);
+                    descriptions.insert(state.clone(), entry.description(instance_register));
+                    if spec.optional {
+                        optional.insert(state.clone());
+                    }
+                    states.insert(spec.name, state);
+                }
+                instances.push(states);
+            }
+            modules.insert(kind, instances);
+        }
+
+        if !problems.is_empty() {
+            return Err(RegisterMapError::InvalidMap(problems));
+        }
+        Ok(Self {
+            prefix: file.prefix,
+            general,
+            modules,
+            descriptions,
+            optional,
+        })
+    }
+
+    /// State ID a general field is read from, every field is present once the map is validated
+    pub fn state(&self, field: &str) -> &str {
+        &self.general[field]
+    }
+
+    /// Number of instances of a module that are looked for
+    pub fn instances(&self, kind: ModuleKind) -> usize {
+        self.modules[&kind].len()
+    }
+
+    /// State ID a field of a module instance is read from, `instance` counts from 0
+    pub fn instance_state(&self, kind: ModuleKind, instance: usize, field: &str) -> &str {
+        &self.modules[&kind][instance][field]
+    }
+
+    /// All state IDs of a module instance, used to discover which instances exist
+    pub fn instance_states(&self, kind: ModuleKind, instance: usize) -> impl Iterator<Item = &str> {
+        self.modules[&kind][instance].values().map(String::as_str)
+    }
+
+    /// How the value of a mapped state is stored in the registers
+    pub fn description(&self, state: &str) -> Option<&RegisterDescription> {
+        self.descriptions.get(state)
+    }
+
+    /// Whether a mapped state may be missing without failing the sample
+    pub fn is_optional(&self, state: &str) -> bool {
+        self.optional.contains(state)
+    }
+
+    /// General states that have a register, ordered by register
+    pub fn general_registers(&self) -> Vec<(&str, &RegisterDescription)> {
+        self.with_registers(GENERAL_FIELDS.iter().map(|spec| self.state(spec.name)))
+    }
+
+    /// States of a module instance that have a register, ordered by register
+    pub fn instance_registers(&self, kind: ModuleKind, instance: usize) -> Vec<(&str, &RegisterDescription)> {
+        self.with_registers(self.instance_states(kind, instance))
+    }
+
+    fn with_registers<'a>(&'a self, states: impl Iterator<Item = &'a str>) -> Vec<(&'a str, &'a RegisterDescription)> {
+        let mut registers: Vec<_> = states
+            .map(|state| (state, &self.descriptions[state]))
+            .filter(|(_, description)| description.register.is_some())
+            .collect();
+        registers.sort_by_key(|(_, description)| description.register);
+        registers
+    }
+
+    /// Unit of every field that has one, keyed by the field for general fields
+    /// and by section and field for modules, e.g. `heatpump.flowline_temp`
+    pub fn units(&self) -> BTreeMap<String, Unit> {
+        let mut units = BTreeMap::new();
+        for spec in GENERAL_FIELDS {
+            if let Some(unit) = self.descriptions[self.state(spec.name)].unit {
+                units.insert(spec.name.to_string(), unit);
+            }
+        }
+        for kind in ModuleKind::ALL {
+            for spec in kind.fields() {
+                if let Some(unit) = self.descriptions[self.instance_state(kind, 0, spec.name)].unit {
+                    units.insert(format!("{}.{}", kind.section(), spec.name), unit);
+                }
+            }
+        }
+        units
+    }
+
+    /// ioBroker patterns covering every mapped state, to subscribe to
+    pub fn subscription_patterns(&self) -> Vec<String> {
+        match self.prefix.is_empty() {
+            false => vec![format!("{}*", self.prefix)],
+            // Without a common prefix the states may belong to any adapter
+            true => self.state_ids().into_iter().map(String::from).collect(),
+        }
+    }
+
+    /// All mapped state IDs, so only these have to be fetched
+    pub fn state_ids(&self) -> Vec<&str> {
+        let mut ids: Vec<&str> = GENERAL_FIELDS.iter().map(|spec| self.state(spec.name)).collect();
+        for kind in ModuleKind::ALL {
+            for instance in 0..self.instances(kind) {
+                ids.extend(kind.fields().iter().map(|spec| self.instance_state(kind, instance, spec.name)));
+            }
+        }
+        ids
+    }
+}
+
+// Checks a section against the fields the mapper expects and returns the entries of the known ones
+fn checked_fields<'a>(
+    section: &str,
+    entries: &'a BTreeMap<String, FieldEntry>,
+    expected: &'static [FieldSpec],
+    problems: &mut Vec<String>,
+) -> Vec<(&'static FieldSpec, &'a FieldEntry)> {
+    for field in entries.keys() {
+        if !expected.iter().any(|spec| spec.name == field) {
+            problems.push(format!("unknown field {}.{}", section, field));
+        }
+    }
+
+    let mut fields = Vec::new();
+    for spec in expected {
+        let (field, value_type) = (spec.name, spec.value_type);
+        let Some(entry) = entries.get(field) else {
+            problems.push(format!("missing field {}.{}", section, field));
+            continue;
+        };
+        if entry.value_type != value_type {
+            problems.push(format!("{}.{} has type {}, expected {}", section, field, entry.value_type, value_type));
+        }
+        if entry.scale.is_some_and(|scale| !scale.is_finite() || scale <= 0.0) {
+            problems.push(format!("{}.{} needs a positive scale", section, field));
+        }
+        // Scaled values have decimals, which only float fields can hold
+        if value_type != ValueType::Float && entry.scale.is_some_and(|scale| scale != 1.0) {
+            problems.push(format!("{}.{} has type {} and cannot be scaled, use type float", section, field, value_type));
+        }
+        if entry.register.is_some_and(|register| register < HOLDING_REGISTER_OFFSET) {
+            problems.push(format!(
+                "{}.{} register must be {} or above, holding registers are numbered like in the ioBroker modbus adapter",
+                section, field, HOLDING_REGISTER_OFFSET
+            ));
+        }
+        if entry.words.is_some_and(|words| words != 1 && words != 2) {
+            problems.push(format!("{}.{} must span 1 or 2 words", section, field));
+        }
+        if entry.state.trim().is_empty() {
+            problems.push(format!("{}.{} has an empty state", section, field));
+            continue;
+        }
+        fields.push((spec, entry));
+    }
+    fields
+}
+
+// Checks that the registers of every instance, including the second word of 32-bit values, are within range
+fn check_register_range(section: &str, fields: &[(&FieldSpec, &FieldEntry)], instances: usize, problems: &mut Vec<String>) {
+    let last_instance = instances.saturating_sub(1);
+    for (spec, entry) in fields {
+        let Some(register) = entry.register else {
+            continue;
+        };
+        let words = entry.words.unwrap_or(1);
+        if instance_register(register, last_instance, words).is_none() {
+            let last_register = register as usize + last_instance * INSTANCE_REGISTER_STEP as usize + words.max(1) as usize - 1;
+            problems.push(format!(
+                "{}.{} of instance {} ends at register {}, beyond {}",
+                section,
+                spec.name,
+                last_instance + 1,
+                last_register,
+                u16::MAX
+            ));
+        }
+    }
+}
+
+// Register of a module instance, None when it or the last word of its value is beyond the register range
+fn instance_register(register: u16, instance: usize, words: u16) -> Option<u16> {
+    let register = u16::try_from(instance)
+        .ok()?
+        .checked_mul(INSTANCE_REGISTER_STEP)?
+        .checked_add(register)?;
+    register.checked_add(words.saturating_sub(1))?;
+    Some(register)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(words: u16, signed: bool, word_order: WordOrder) -> RegisterDescription {
+        RegisterDescription {
+            register: Some(41021),
+            scale: 1.0,
+            signed,
+            words,
+            word_order,
+            unit: None,
+        }
+    }
+
+    // The bundled map with one entry replaced, the problems it is rejected with
+    fn problems_with(from: &str, to: &str) -> Vec<String> {
+        assert!(BUNDLED_MAP.contains(from), "{} is not in the bundled map", from);
+        match RegisterMap::from_toml(&BUNDLED_MAP.replacen(from, to, 1)) {
+            Err(RegisterMapError::InvalidMap(problems)) => problems,
+            other => panic!("expected an invalid map, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn decode_single_words_by_sign() {
+        assert_eq!(description(1, true, WordOrder::HighFirst).decode(&[0xFFFF]), -1);
+        assert_eq!(description(1, false, WordOrder::HighFirst).decode(&[0xFFFF]), 65535);
+        assert_eq!(description(1, true, WordOrder::HighFirst).decode(&[215]), 215);
+    }
+
+    #[test]
+    fn decode_double_words_in_word_order() {
+        assert_eq!(description(2, false, WordOrder::HighFirst).decode(&[0x0001, 0x0002]), 0x0001_0002);
+        assert_eq!(description(2, false, WordOrder::LowFirst).decode(&[0x0002, 0x0001]), 0x0001_0002);
+        assert_eq!(description(2, true, WordOrder::HighFirst).decode(&[0xFFFF, 0xFFFE]), -2);
+        assert_eq!(description(2, true, WordOrder::LowFirst).decode(&[0xFFFE, 0xFFFF]), -2);
+        assert_eq!(description(2, false, WordOrder::HighFirst).decode(&[0xFFFF, 0xFFFE]), 0xFFFF_FFFE);
+    }
+
+    #[test]
+    fn scaled_keeps_the_decimals_of_the_scale() {
+        let mut description = description(1, true, WordOrder::HighFirst);
+        assert_eq!(description.scaled(42), "42");
+        description.scale = 0.1;
+        assert_eq!(description.scaled(215), "21.5");
+        assert_eq!(description.scaled(-3), "-0.3");
+        description.scale = 0.01;
+        assert_eq!(description.scaled(-5), "-0.05");
+        assert_eq!(description.scaled(2000), "20.00");
+    }
+
+    #[test]
+    fn from_toml_rejects_registers_below_the_offset() {
+        let problems = problems_with("register = 40002,", "register = 2,");
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("general.ambient_state register must be 40001 or above"));
+    }
+
+    #[test]
+    fn from_toml_rejects_scaled_integer_fields() {
+        let problems = problems_with(
+            r#"state = "{register}_HP{n}_Error", type = "integer""#,
+            r#"state = "{register}_HP{n}_Error", type = "integer", scale = 0.1"#,
+        );
+        assert_eq!(problems, ["heatpump.error_number has type integer and cannot be scaled, use type float"]);
+    }
+
+    #[test]
+    fn from_toml_rejects_registers_beyond_the_range() {
+        // 12 heating circuits put the last instance 1100 registers above the first
+        let problems = problems_with("register = 45053,", "register = 64500,");
+        assert_eq!(problems, ["heating_circuit.room_cooling_setpoint of instance 12 ends at register 65600, beyond 65535"]);
+
+        let problems = problems_with(
+            r#"register = 40104, state = "40104_E_Manager_Actual", type = "float""#,
+            r#"register = 65535, state = "40104_E_Manager_Actual", type = "float", words = 2"#,
+        );
+        assert_eq!(problems, ["general.emanager_actual_power of instance 1 ends at register 65536, beyond 65535"]);
+    }
+
+    #[test]
+    fn from_toml_reads_the_bundled_map() {
+        let map = RegisterMap::bundled().unwrap();
+        assert_eq!(map.state("ambient_state"), "modbus.0.holdingRegisters.40002_Ambient_State");
+        assert_eq!(map.instances(ModuleKind::HeatPump), 3);
+        assert_eq!(
+            map.instance_state(ModuleKind::HeatPump, 1, "state"),
+            "modbus.0.holdingRegisters.41103_HP2_State"
+        );
+        let description = map.description("modbus.0.holdingRegisters.41123_HP2_VdA_Q").unwrap();
+        assert_eq!((description.register, description.words), (Some(41123), 2));
+        assert!(map.is_optional("modbus.0.holdingRegisters.41120_HP2_SecondStage"));
+        assert!(!map.is_optional("modbus.0.holdingRegisters.41103_HP2_State"));
+        assert_eq!(map.subscription_patterns(), ["modbus.0.holdingRegisters.*"]);
+    }
+
+    #[test]
+    fn from_toml_subscribes_to_every_state_without_prefix() {
+        let map = RegisterMap::from_toml(&BUNDLED_MAP.replacen(r#"prefix = "modbus.0.holdingRegisters.""#, "", 1)).unwrap();
+        assert_eq!(map.state("ambient_state"), "40002_Ambient_State");
+        assert_eq!(map.subscription_patterns().len(), map.state_ids().len());
+        assert!(map.subscription_patterns().contains(&String::from("45253_Heating3_SetpointT_RoomCooling")));
+    }
+
+    #[test]
+    fn from_toml_reports_missing_and_unknown_fields() {
+        let problems = problems_with("ambient_state = ", "ambient_mood = ");
+        assert_eq!(problems, ["unknown field general.ambient_mood", "missing field general.ambient_state"]);
+    }
+
+    #[test]
+    fn from_toml_reports_wrong_types() {
+        let problems = problems_with(
+            r#"state = "40002_Ambient_State", type = "enum""#,
+            r#"state = "40002_Ambient_State", type = "float""#,
+        );
+        assert_eq!(problems, ["general.ambient_state has type float, expected enum"]);
+    }
+
+    #[test]
+    fn from_toml_reports_states_mapped_twice() {
+        let problems = problems_with(r#"state = "40005_Ambient_Calculated_Temp""#, r#"state = "40004_Ambient_Temp_24h""#);
+        assert_eq!(problems, ["state modbus.0.holdingRegisters.40004_Ambient_Temp_24h is mapped more than once"]);
+    }
+
+    #[test]
+    fn from_toml_reports_instances_out_of_range() {
+        let problems = problems_with("[solar]\ninstances = 2", "[solar]\ninstances = 3");
+        assert_eq!(problems, ["solar instances must be between 1 and 2"]);
+        let problems = problems_with("[boiler]\ninstances = 5", "[boiler]\ninstances = 0");
+        assert_eq!(problems, ["boiler instances must be between 1 and 5"]);
+    }
+
+    #[test]
+    fn from_toml_reports_register_placeholders_without_register() {
+        let problems = problems_with("register = 42002, ", "");
+        assert_eq!(problems, ["boiler.state uses {register} without a register"]);
+    }
+}
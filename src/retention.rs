@@ -0,0 +1,206 @@
+use crate::postgres_client::PostgresClient;
+use chrono::{DateTime, Utc};
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, Statement};
+use tokio::time::{self, Duration};
+
+// Heat pump rows inserted up to the rollup watermark are contained in the heat pump rollups,
+// unless they arrived for a day the rollup job left alone because it was already pruned
+const HEATPUMP_COVERED: &str =
+    "t.inserted_at <= (SELECT rolled_up_until FROM rollup_state WHERE name = 'heatpump') \
+     AND NOT EXISTS (SELECT FROM rollup_skipped s WHERE s.name = 'heatpump' \
+         AND t.event_timestamp >= s.day AND t.event_timestamp < s.day + INTERVAL '1 day')";
+
+// Module rows are written together with their heat pump row and go once it is rolled up or gone
+const MODULE_COVERED: &str = "NOT EXISTS (SELECT FROM heatpump h WHERE h.event_timestamp = t.event_timestamp \
+     AND (h.inserted_at > COALESCE((SELECT rolled_up_until FROM rollup_state WHERE name = 'heatpump'), '-infinity') \
+         OR EXISTS (SELECT FROM rollup_skipped s WHERE s.name = 'heatpump' \
+             AND h.event_timestamp >= s.day AND h.event_timestamp < s.day + INTERVAL '1 day')))";
+
+// Sensor readings inserted up to the rollup watermark are contained in the sensor rollups,
+// unless they arrived for a day the rollup job left alone because it was already pruned
+const SENSOR_COVERED: &str =
+    "t.inserted_at <= (SELECT rolled_up_until FROM rollup_state WHERE name = 'sensor_reading') \
+     AND NOT EXISTS (SELECT FROM rollup_skipped s WHERE s.name = 'sensor_reading' \
+         AND t.event_timestamp >= s.day AND t.event_timestamp < s.day + INTERVAL '1 day')";
+
+// Table with its primary key and the condition under which a row is covered elsewhere
+type PrunedTable = (&'static str, &'static str, &'static str);
+
+/// Raw tables that may be pruned, each with the tables deleted from in this order.
+/// Rollup tables and tables nothing else covers are kept forever.
+const RETAINED_TABLES: [(&str, &[PrunedTable]); 2] = [
+    (
+        "heatpump",
+        &[
+            ("heatpump_unit", "event_timestamp, module_index", MODULE_COVERED),
+            ("boiler", "event_timestamp, module_index", MODULE_COVERED),
+            ("buffer", "event_timestamp, module_index", MODULE_COVERED),
+            ("solar_module", "event_timestamp, module_index", MODULE_COVERED),
+            ("heating_circuit", "event_timestamp, module_index", MODULE_COVERED),
+            ("heatpump", "event_timestamp", HEATPUMP_COVERED),
+        ],
+    ),
+    (
+        "sensor_reading",
+        &[("sensor_reading", "device_id, quantity, event_timestamp", SENSOR_COVERED)],
+    ),
+];
+
+/// Pause between two batches, so writers waiting for the table get their turn
+const BATCH_PAUSE: Duration = Duration::from_millis(200);
+
+/// How long the raw rows of a table are kept
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub table: &'static str,
+    pub keep: chrono::Duration,
+}
+
+impl RetentionPolicy {
+    pub fn new(table: &str, keep_days: i64) -> Result<Self, String> {
+        // The view shows the readings of `sensor_reading`, its former name stays valid
+        let table = if table == "temperature_data" { "sensor_reading" } else { table };
+        let (table, _) = RETAINED_TABLES
+            .iter()
+            .find(|(name, _)| *name == table)
+            .ok_or_else(|| format!("no retention for table {}, only raw tables covered by rollups can be pruned", table))?;
+        if keep_days <= 0 {
+            return Err(format!("retention of {} must be at least one day", table));
+        }
+        Ok(Self {
+            table,
+            keep: chrono::Duration::days(keep_days),
+        })
+    }
+}
+
+/// Deletes raw rows older than their retention in the background
+pub struct RetentionJob {
+    policies: Vec<RetentionPolicy>,
+    batch_size: u64,
+    interval: Duration,
+}
+
+impl RetentionJob {
+    pub fn new(policies: Vec<RetentionPolicy>, batch_size: u64, interval: Duration) -> Self {
+        Self {
+            policies,
+            batch_size,
+            interval,
+        }
+    }
+
+    pub async fn run(self, database_client: PostgresClient) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            for policy in &self.policies {
+                match database_client.apply_retention(policy, self.batch_size).await {
+                    Ok(rows) => println!("Retention of {}: {} rows deleted", policy.table, rows),
+                    Err(e) => eprintln!("Error applying retention of {}: {}", policy.table, e),
+                }
+            }
+        }
+    }
+}
+
+/// Deletes the covered rows of the table and the tables pruned with it that are older than its retention,
+/// `batch_size` rows per statement, and returns the number of rows deleted
+pub async fn apply<C: ConnectionTrait>(db: &C, policy: &RetentionPolicy, batch_size: u64) -> Result<u64, DbErr> {
+    let (_, tables) = RETAINED_TABLES
+        .iter()
+        .find(|(name, _)| *name == policy.table)
+        .ok_or_else(|| DbErr::Custom(format!("no retention for table {}", policy.table)))?;
+    let cutoff = Utc::now() - policy.keep;
+    // Before the first rollup run nothing is covered, the rollup job has to roll up the old rows first
+    if !has_covered_rows(db, tables, cutoff).await? {
+        return Ok(0);
+    }
+    record_deleted_before(db, policy.table, cutoff).await?;
+
+    let mut deleted = 0;
+    for (table, key, covered) in tables.iter() {
+        deleted += delete_batches(db, table, key, covered, cutoff, batch_size).await?;
+    }
+    Ok(deleted)
+}
+
+// Deleting by primary key, ctid is not unique across the chunks of a hypertable
+async fn delete_batches<C: ConnectionTrait>(
+    db: &C,
+    table: &str,
+    key: &str,
+    covered: &str,
+    cutoff: DateTime<Utc>,
+    batch_size: u64,
+) -> Result<u64, DbErr> {
+    let sql = format!(
+        "DELETE FROM {table} WHERE ({key}) IN ( \
+             SELECT {key} FROM {table} t \
+             WHERE t.event_timestamp < $1 AND {covered} \
+             ORDER BY t.event_timestamp LIMIT {batch_size} \
+         )"
+    );
+    let mut deleted = 0;
+    loop {
+        let statement = Statement::from_sql_and_values(DatabaseBackend::Postgres, &sql, [cutoff.into()]);
+        let rows = db.execute(statement).await?.rows_affected();
+        deleted += rows;
+        if rows < batch_size {
+            return Ok(deleted);
+        }
+        time::sleep(BATCH_PAUSE).await;
+    }
+}
+
+async fn has_covered_rows<C: ConnectionTrait>(db: &C, tables: &[PrunedTable], cutoff: DateTime<Utc>) -> Result<bool, DbErr> {
+    for (table, _, covered) in tables {
+        let statement = Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            format!("SELECT EXISTS (SELECT FROM {table} t WHERE t.event_timestamp < $1 AND {covered}) AS covered"),
+            [cutoff.into()],
+        );
+        if let Some(row) = db.query_one(statement).await?
+            && row.try_get::<bool>("", "covered")?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Stored before deleting, so the rollup job stops recomputing these buckets before rows disappear
+async fn record_deleted_before<C: ConnectionTrait>(db: &C, table: &str, cutoff: DateTime<Utc>) -> Result<(), DbErr> {
+    db.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        "INSERT INTO retention_state (table_name, deleted_before) VALUES ($1, $2) \
+         ON CONFLICT (table_name) DO UPDATE SET deleted_before = GREATEST(retention_state.deleted_before, EXCLUDED.deleted_before)",
+        [table.into(), cutoff.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_accepts_the_tables_covered_by_rollups() {
+        let policy = RetentionPolicy::new("heatpump", 90).unwrap();
+        assert_eq!((policy.table, policy.keep), ("heatpump", chrono::Duration::days(90)));
+        assert_eq!(RetentionPolicy::new("sensor_reading", 730).unwrap().table, "sensor_reading");
+    }
+
+    #[test]
+    fn policy_for_temperature_data_prunes_sensor_reading() {
+        assert_eq!(RetentionPolicy::new("temperature_data", 365).unwrap().table, "sensor_reading");
+    }
+
+    #[test]
+    fn policy_rejects_other_tables_and_empty_retention() {
+        assert!(RetentionPolicy::new("heatpump_daily", 90).is_err());
+        assert!(RetentionPolicy::new("boiler", 90).is_err());
+        assert!(RetentionPolicy::new("heatpump", 0).is_err());
+    }
+}
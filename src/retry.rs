@@ -0,0 +1,253 @@
+use rand::Rng;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often and how fast failed requests are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Random spread applied to every backoff, 0.2 means ±20%
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the given retry, starting at 0 for the first one
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.powi(retry as i32);
+        let capped = base.min(self.max_backoff.as_secs_f64());
+        let spread = if self.jitter > 0.0 {
+            rand::rng().random_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+        Duration::from_secs_f64((capped * (1.0 + spread)).max(0.0))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed requests after which the circuit opens
+    pub failure_threshold: u32,
+    /// How long requests are refused before a single trial request is let through
+    pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    // When the circuit opened or, while half-open, the trial request was let through
+    opened_at: Option<Instant>,
+}
+
+/// Stops requests to a service that keeps failing and probes it again after a while.
+/// Clones share the same state.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    name: String,
+    config: CircuitBreakerConfig,
+    inner: Arc<Mutex<BreakerState>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: String, config: CircuitBreakerConfig) -> Self {
+        Self {
+            name,
+            config,
+            inner: Arc::new(Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+            })),
+        }
+    }
+
+    /// Whether a request may be sent, otherwise the time until the next trial request.
+    /// While half-open only the trial is let through, another one only once the trial
+    /// did not report back within the open duration.
+    pub fn allow_request(&self) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == CircuitState::Closed {
+            return Ok(());
+        }
+
+        let elapsed = inner.opened_at.map(|at| at.elapsed()).unwrap_or_default();
+        if elapsed < self.config.open_duration {
+            return Err(self.config.open_duration - elapsed);
+        }
+        inner.opened_at = Some(Instant::now());
+        if inner.state == CircuitState::Open {
+            self.transition(&mut inner, CircuitState::HalfOpen);
+        }
+        Ok(())
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if inner.state != CircuitState::Closed {
+            self.transition(&mut inner, CircuitState::Closed);
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let trips = inner.state == CircuitState::HalfOpen
+            || (inner.state == CircuitState::Closed
+                && inner.consecutive_failures >= self.config.failure_threshold);
+        if trips {
+            inner.opened_at = Some(Instant::now());
+            self.transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    fn transition(&self, inner: &mut BreakerState, state: CircuitState) {
+        println!(
+            "Circuit breaker {}: {} -> {} after {} consecutive failures",
+            self.name, inner.state, state, inner.consecutive_failures
+        );
+        inner.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(failure_threshold: u32, open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(String::from("test"), CircuitBreakerConfig { failure_threshold, open_duration })
+    }
+
+    fn state(breaker: &CircuitBreaker) -> CircuitState {
+        breaker.inner.lock().unwrap().state
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(5), Duration::from_secs(10));
+        assert_eq!(policy.backoff(40), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn backoff_jitter_stays_within_its_spread() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let backoff = policy.backoff(1);
+            assert!(backoff >= Duration::from_millis(800) && backoff <= Duration::from_millis(1200), "{:?}", backoff);
+        }
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures() {
+        let breaker = breaker(3, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert_eq!(state(&breaker), CircuitState::Closed);
+        assert!(breaker.allow_request().is_ok());
+
+        breaker.record_failure();
+        assert_eq!(state(&breaker), CircuitState::Open);
+        let wait = breaker.allow_request().unwrap_err();
+        assert!(wait > Duration::from_secs(59) && wait <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn breaker_lets_one_trial_through_after_the_open_duration() {
+        let open_duration = Duration::from_millis(50);
+        let breaker = breaker(1, open_duration);
+        breaker.record_failure();
+        assert_eq!(state(&breaker), CircuitState::Open);
+        assert!(breaker.allow_request().is_err());
+
+        std::thread::sleep(open_duration);
+        assert!(breaker.allow_request().is_ok());
+        assert_eq!(state(&breaker), CircuitState::HalfOpen);
+        // Other requests wait for the trial
+        assert!(breaker.allow_request().is_err());
+        // A failed trial opens the circuit again, a successful one closes it
+        breaker.record_failure();
+        assert_eq!(state(&breaker), CircuitState::Open);
+        assert!(breaker.allow_request().is_err());
+        std::thread::sleep(open_duration);
+        assert!(breaker.allow_request().is_ok());
+        breaker.record_success();
+        assert_eq!(state(&breaker), CircuitState::Closed);
+        assert!(breaker.allow_request().is_ok());
+        assert!(breaker.allow_request().is_ok());
+    }
+
+    #[test]
+    fn breaker_lets_another_trial_through_when_the_trial_never_reports_back() {
+        let open_duration = Duration::from_millis(50);
+        let breaker = breaker(1, open_duration);
+        breaker.record_failure();
+        std::thread::sleep(open_duration);
+        assert!(breaker.allow_request().is_ok());
+        assert!(breaker.allow_request().is_err());
+
+        std::thread::sleep(open_duration);
+        assert!(breaker.allow_request().is_ok());
+        assert_eq!(state(&breaker), CircuitState::HalfOpen);
+        assert!(breaker.allow_request().is_err());
+    }
+
+    #[test]
+    fn clones_share_the_breaker_state() {
+        let breaker = breaker(1, Duration::from_secs(60));
+        let clone = breaker.clone();
+        clone.record_failure();
+        assert!(breaker.allow_request().is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::postgres_client::PostgresClient;
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, Statement, TransactionTrait};
+use tokio::time::{self, Duration};
+
+/// Rollup tables with the width of their buckets, buckets start at midnight UTC
+const ROLLUPS: [(&str, &str); 3] = [
+    ("heatpump_5min", "5 minutes"),
+    ("heatpump_hourly", "1 hour"),
+    ("heatpump_daily", "1 day"),
+];
+
+/// Sensors report every 15 minutes, so there are no 5 minute buckets for them
+const SENSOR_ROLLUPS: [(&str, &str); 2] = [
+    ("sensor_reading_hourly", "1 hour"),
+    ("sensor_reading_daily", "1 day"),
+];
+
+/// Columns rolled up into min, max and avg
+const NUMERIC_COLUMNS: [&str; 23] = [
+    "ambient_temperature1h",
+    "ambient_temperature24h",
+    "ambient_temperaturecalculated",
+    "boiler_hightemp",
+    "boiler_lowtemp",
+    "boiler_maxtemp",
+    "buffer_hightemp",
+    "buffer_lowtemp",
+    "buffer_maxtemp",
+    "heatingcircuit_1_flowtemp",
+    "heatingcircuit_2_flowtemp",
+    "heatpump_actualheatingcapacity",
+    "heatpump_compressorrating",
+    "heatpump_currentcop",
+    "heatpump_energysourceinlettemp",
+    "heatpump_flowlinetemp",
+    "heatpump_inverteractualpower",
+    "heatpump_requestflowtemp",
+    "heatpump_requestreturntemp",
+    "heatpump_requesttempdiff",
+    "heatpump_returnlinetemp",
+    "heatpump_volumesink",
+    "heatpump_volumesourceflow",
+];
+
+/// Columns rolled up into their most frequent value
+const STATE_COLUMNS: [&str; 9] = [
+    "ambient_state",
+    "boiler_state",
+    "buffer_state",
+    "heatingcircuit_1_state",
+    "heatingcircuit_2_state",
+    "heatpump_errorstate",
+    "heatpump_operatingstate",
+    "heatpump_requesttype",
+    "heatpump_state",
+];
+
+/// Energy counters with the column of their delta
+const COUNTER_COLUMNS: [(&str, &str); 2] = [
+    ("heatpump_heatenergy", "heat_energy_delta"),
+    ("heatpump_electricenergy", "electric_energy_delta"),
+];
+
+/// Rows are rolled up once they are this old, so writes still in an open transaction are not missed
+const SETTLE_TIME: &str = "1 minute";
+
+/// Keeps the heat pump and sensor rollup tables up to date for databases without TimescaleDB
+pub struct RollupJob {
+    interval: Duration,
+}
+
+impl RollupJob {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+
+    pub async fn run(self, database_client: PostgresClient) {
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            match database_client.refresh_rollups().await {
+                Ok(buckets) => println!("Rollups refreshed, {} buckets updated", buckets),
+                Err(e) => eprintln!("Error refreshing rollups: {}", e),
+            }
+        }
+    }
+}
+
+/// Recomputes every bucket that received raw rows since the last run, late rows included,
+/// and returns the number of buckets written
+pub async fn refresh<C: TransactionTrait>(db: &C) -> Result<u64, DbErr> {
+    let txn = db.begin().await?;
+    let until = settled_until(&txn).await?;
+    let mut buckets = refresh_source(&txn, "heatpump", &ROLLUPS, rollup_sql, until).await?;
+    buckets += refresh_source(&txn, "sensor_reading", &SENSOR_ROLLUPS, sensor_rollup_sql, until).await?;
+    txn.commit().await?;
+    Ok(buckets)
+}
+
+// Rolls up the rows of the raw table inserted since its last run and records how far it got
+async fn refresh_source<C: ConnectionTrait>(
+    db: &C,
+    source: &str,
+    rollups: &[(&str, &str)],
+    sql: fn(&str, &str) -> String,
+    until: DateTime<FixedOffset>,
+) -> Result<u64, DbErr> {
+    let since = rolled_up_until(db, source).await?;
+    let deleted_before = deleted_before(db, source).await?;
+
+    db.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        skipped_sql(source),
+        [since.into(), until.into(), deleted_before.into()],
+    ))
+    .await?;
+    let mut buckets = 0;
+    for (table, width) in rollups {
+        let statement = Statement::from_sql_and_values(
+            DatabaseBackend::Postgres,
+            sql(table, width),
+            [since.into(), until.into(), deleted_before.into()],
+        );
+        buckets += db.execute(statement).await?.rows_affected();
+    }
+    db.execute(Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        "INSERT INTO rollup_state (name, rolled_up_until) VALUES ($1, $2) \
+         ON CONFLICT (name) DO UPDATE SET rolled_up_until = EXCLUDED.rolled_up_until",
+        [source.into(), until.into()],
+    ))
+    .await?;
+    Ok(buckets)
+}
+
+/// Raw rows of the table inserted up to this time are contained in its rollups
+pub async fn rolled_up_until<C: ConnectionTrait>(db: &C, source: &str) -> Result<DateTime<FixedOffset>, DbErr> {
+    let statement = Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        "SELECT rolled_up_until FROM rollup_state WHERE name = $1",
+        [source.into()],
+    );
+    match db.query_one(statement).await? {
+        Some(row) => row.try_get("", "rolled_up_until"),
+        None => Ok(DateTime::<Utc>::UNIX_EPOCH.fixed_offset()),
+    }
+}
+
+// Buckets before this lost raw rows to the retention job, recomputing them would lose data
+async fn deleted_before<C: ConnectionTrait>(db: &C, source: &str) -> Result<DateTime<FixedOffset>, DbErr> {
+    let statement = Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        "SELECT deleted_before FROM retention_state WHERE table_name = $1",
+        [source.into()],
+    );
+    match db.query_one(statement).await? {
+        Some(row) => row.try_get("", "deleted_before"),
+        None => Ok(DateTime::<Utc>::UNIX_EPOCH.fixed_offset()),
+    }
+}
+
+// Taken from the database clock, which also sets inserted_at
+async fn settled_until<C: ConnectionTrait>(db: &C) -> Result<DateTime<FixedOffset>, DbErr> {
+    let statement = Statement::from_string(
+        DatabaseBackend::Postgres,
+        format!("SELECT now() - INTERVAL '{SETTLE_TIME}' AS until"),
+    );
+    match db.query_one(statement).await? {
+        Some(row) => row.try_get("", "until"),
+        None => Err(DbErr::RecordNotFound(String::from("database time"))),
+    }
+}
+
+// Records the days of the rows inserted between $1 and $2 that start before $3. Their buckets are
+// not recomputed, a day is the widest bucket so every row of a skipped bucket lies in a recorded day.
+fn skipped_sql(source: &str) -> String {
+    format!(
+        "INSERT INTO rollup_skipped (name, day) \
+         SELECT DISTINCT '{source}', date_bin(INTERVAL '1 day', event_timestamp, TIMESTAMPTZ '2000-01-01 00:00:00+00') AS day \
+         FROM {source} WHERE inserted_at > $1 AND inserted_at <= $2 \
+             AND date_bin(INTERVAL '1 day', event_timestamp, TIMESTAMPTZ '2000-01-01 00:00:00+00') < $3 \
+         ON CONFLICT DO NOTHING"
+    )
+}
+
+// Upserts the buckets with rows inserted between $1 and $2 and the buckets after them,
+// whose energy deltas start at the last counter reading of the changed bucket.
+// Buckets starting before $3 are left alone, their raw rows are partly deleted.
+fn rollup_sql(table: &str, width: &str) -> String {
+    let mut columns = vec![String::from("samples")];
+    let mut values = vec![String::from("count(*)")];
+    for column in NUMERIC_COLUMNS {
+        for aggregate in ["min", "max", "avg"] {
+            columns.push(format!("{column}_{aggregate}"));
+            values.push(format!("{aggregate}(h.{column})"));
+        }
+    }
+    for column in STATE_COLUMNS {
+        columns.push(column.to_string());
+        values.push(format!("mode() WITHIN GROUP (ORDER BY h.{column})"));
+    }
+    for (counter, delta) in COUNTER_COLUMNS {
+        columns.push(delta.to_string());
+        // The first bucket without an earlier reading only counts its own increase
+        values.push(format!(
+            "max(h.{counter}) - COALESCE((SELECT p.{counter} FROM heatpump p \
+             WHERE p.event_timestamp < a.bucket AND p.{counter} IS NOT NULL \
+             ORDER BY p.event_timestamp DESC LIMIT 1), min(h.{counter}))"
+        ));
+    }
+    let updates: Vec<String> = columns
+        .iter()
+        .map(|column| format!("{column} = EXCLUDED.{column}"))
+        .collect();
+
+    format!(
+        "WITH dirty AS ( \
+             SELECT DISTINCT date_bin(INTERVAL '{width}', event_timestamp, TIMESTAMPTZ '2000-01-01 00:00:00+00') AS bucket \
+             FROM heatpump WHERE inserted_at > $1 AND inserted_at <= $2 \
+         ), affected AS ( \
+             SELECT bucket FROM dirty UNION SELECT bucket + INTERVAL '{width}' FROM dirty \
+         ), kept AS ( \
+             SELECT bucket FROM affected WHERE bucket >= $3 \
+         ) \
+         INSERT INTO {table} (bucket, {columns}) \
+         SELECT a.bucket, {values} \
+         FROM kept a \
+         JOIN heatpump h ON h.event_timestamp >= a.bucket AND h.event_timestamp < a.bucket + INTERVAL '{width}' \
+         GROUP BY a.bucket \
+         ON CONFLICT (bucket) DO UPDATE SET {updates}",
+        columns = columns.join(", "),
+        values = values.join(", "),
+        updates = updates.join(", "),
+    )
+}
+
+// Upserts the buckets of every device and quantity with readings inserted between $1 and $2.
+// Buckets starting before $3 are left alone, their raw rows are partly deleted.
+fn sensor_rollup_sql(table: &str, width: &str) -> String {
+    format!(
+        "WITH dirty AS ( \
+             SELECT DISTINCT date_bin(INTERVAL '{width}', event_timestamp, TIMESTAMPTZ '2000-01-01 00:00:00+00') AS bucket, \
+                 device_id, quantity \
+             FROM sensor_reading WHERE inserted_at > $1 AND inserted_at <= $2 \
+         ) \
+         INSERT INTO {table} (bucket, device_id, quantity, samples, value, min_value, max_value) \
+         SELECT d.bucket, d.device_id, d.quantity, count(*), avg(r.value), min(r.value), max(r.value) \
+         FROM dirty d \
+         JOIN sensor_reading r ON r.device_id = d.device_id AND r.quantity = d.quantity \
+             AND r.event_timestamp >= d.bucket AND r.event_timestamp < d.bucket + INTERVAL '{width}' \
+         WHERE d.bucket >= $3 \
+         GROUP BY d.bucket, d.device_id, d.quantity \
+         ON CONFLICT (device_id, quantity, bucket) DO UPDATE SET samples = EXCLUDED.samples, value = EXCLUDED.value, \
+             min_value = EXCLUDED.min_value, max_value = EXCLUDED.max_value"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{MockDatabase, MockExecResult, Transaction, Value};
+    use std::collections::BTreeMap;
+
+    fn timestamp(value: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(value).unwrap()
+    }
+
+    fn row(column: &str, value: DateTime<FixedOffset>) -> Vec<BTreeMap<String, Value>> {
+        vec![BTreeMap::from([(column.to_string(), Value::from(value))])]
+    }
+
+    fn statement(sql: String, values: Vec<Value>) -> Statement {
+        Statement::from_sql_and_values(DatabaseBackend::Postgres, sql, values)
+    }
+
+    fn record(source: &str, until: DateTime<FixedOffset>) -> Statement {
+        statement(
+            String::from(
+                "INSERT INTO rollup_state (name, rolled_up_until) VALUES ($1, $2) \
+                 ON CONFLICT (name) DO UPDATE SET rolled_up_until = EXCLUDED.rolled_up_until",
+            ),
+            vec![source.into(), until.into()],
+        )
+    }
+
+    fn lookup(sql: &str, source: &str) -> Statement {
+        statement(String::from(sql), vec![source.into()])
+    }
+
+    #[tokio::test]
+    async fn refresh_rolls_up_from_the_last_watermark_and_records_the_new_one() {
+        let until = timestamp("2025-10-18T12:00:00+00:00");
+        let heatpump_since = timestamp("2025-10-18T11:55:00+00:00");
+        let heatpump_deleted_before = timestamp("2025-07-20T00:00:00+00:00");
+        let epoch = DateTime::<Utc>::UNIX_EPOCH.fixed_offset();
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([
+                row("until", until),
+                row("rolled_up_until", heatpump_since),
+                row("deleted_before", heatpump_deleted_before),
+                // sensor_reading was neither rolled up nor pruned yet
+                Vec::new(),
+                Vec::new(),
+            ])
+            .append_exec_results([0, 1, 2, 4, 1, 0, 5, 6, 1].map(|rows| MockExecResult { last_insert_id: 0, rows_affected: rows }))
+            .into_connection();
+
+        assert_eq!(refresh(&db).await.unwrap(), 1 + 2 + 4 + 5 + 6);
+
+        let heatpump = [heatpump_since.into(), until.into(), heatpump_deleted_before.into()];
+        let sensor = [epoch.into(), until.into(), epoch.into()];
+        let mut statements = vec![
+            Statement::from_string(DatabaseBackend::Postgres, "BEGIN"),
+            Statement::from_string(DatabaseBackend::Postgres, format!("SELECT now() - INTERVAL '{SETTLE_TIME}' AS until")),
+            lookup("SELECT rolled_up_until FROM rollup_state WHERE name = $1", "heatpump"),
+            lookup("SELECT deleted_before FROM retention_state WHERE table_name = $1", "heatpump"),
+            statement(skipped_sql("heatpump"), heatpump.to_vec()),
+        ];
+        for (table, width) in ROLLUPS {
+            statements.push(statement(rollup_sql(table, width), heatpump.to_vec()));
+        }
+        statements.push(record("heatpump", until));
+        statements.push(lookup("SELECT rolled_up_until FROM rollup_state WHERE name = $1", "sensor_reading"));
+        statements.push(lookup("SELECT deleted_before FROM retention_state WHERE table_name = $1", "sensor_reading"));
+        statements.push(statement(skipped_sql("sensor_reading"), sensor.to_vec()));
+        for (table, width) in SENSOR_ROLLUPS {
+            statements.push(statement(sensor_rollup_sql(table, width), sensor.to_vec()));
+        }
+        statements.push(record("sensor_reading", until));
+        statements.push(Statement::from_string(DatabaseBackend::Postgres, "COMMIT"));
+
+        assert_eq!(db.into_transaction_log(), [Transaction::many(statements)]);
+    }
+
+    #[tokio::test]
+    async fn refresh_keeps_the_watermark_when_a_rollup_fails() {
+        let until = timestamp("2025-10-18T12:00:00+00:00");
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([row("until", until), Vec::new(), Vec::new()])
+            .append_exec_errors([DbErr::Custom(String::from("deadlock detected"))])
+            .into_connection();
+
+        assert!(refresh(&db).await.is_err());
+        let log = db.into_transaction_log();
+        let statements: Vec<String> = log.iter().flat_map(|transaction| transaction.statements()).map(|s| s.sql.clone()).collect();
+        assert!(!statements.iter().any(|sql| sql.starts_with("INSERT INTO rollup_state")));
+    }
+
+    #[test]
+    fn rollup_sql_recomputes_the_buckets_of_late_rows_and_the_bucket_after_them() {
+        let sql = rollup_sql("heatpump_hourly", "1 hour");
+        // Rows are picked by insertion time, so late rows dirty their own, possibly old bucket
+        assert!(sql.contains(
+            "SELECT DISTINCT date_bin(INTERVAL '1 hour', event_timestamp, TIMESTAMPTZ '2000-01-01 00:00:00+00') AS bucket \
+             FROM heatpump WHERE inserted_at > $1 AND inserted_at <= $2"
+        ));
+        // The next bucket's energy deltas start at the late row's bucket
+        assert!(sql.contains("SELECT bucket FROM dirty UNION SELECT bucket + INTERVAL '1 hour' FROM dirty"));
+        // Every raw row of a recomputed bucket is aggregated, not only the new ones
+        assert!(sql.contains(
+            "JOIN heatpump h ON h.event_timestamp >= a.bucket AND h.event_timestamp < a.bucket + INTERVAL '1 hour'"
+        ));
+        assert!(sql.contains("SELECT bucket FROM affected WHERE bucket >= $3"));
+    }
+
+    #[test]
+    fn rollup_sql_counts_energy_from_the_previous_reading_or_the_first_buckets_own_min() {
+        let sql = rollup_sql("heatpump_5min", "5 minutes");
+        for (counter, delta) in COUNTER_COLUMNS {
+            assert!(sql.contains(&format!(
+                "max(h.{counter}) - COALESCE((SELECT p.{counter} FROM heatpump p \
+                 WHERE p.event_timestamp < a.bucket AND p.{counter} IS NOT NULL \
+                 ORDER BY p.event_timestamp DESC LIMIT 1), min(h.{counter}))"
+            )));
+            assert!(sql.contains(&format!("{delta} = EXCLUDED.{delta}")));
+        }
+    }
+
+    #[test]
+    fn rollup_sql_writes_every_rolled_up_column() {
+        let sql = rollup_sql("heatpump_daily", "1 day");
+        let columns = sql.split("INSERT INTO heatpump_daily (bucket, ").nth(1).unwrap().split(')').next().unwrap();
+        let columns: Vec<&str> = columns.split(", ").collect();
+        assert_eq!(columns.len(), 1 + NUMERIC_COLUMNS.len() * 3 + STATE_COLUMNS.len() + COUNTER_COLUMNS.len());
+        for column in columns {
+            assert!(sql.contains(&format!("{column} = EXCLUDED.{column}")), "{}", column);
+        }
+    }
+
+    #[test]
+    fn skipped_sql_records_the_days_of_late_rows_before_the_retention_cutoff() {
+        let sql = skipped_sql("heatpump");
+        assert!(sql.starts_with("INSERT INTO rollup_skipped (name, day) SELECT DISTINCT 'heatpump', "));
+        assert!(sql.contains("FROM heatpump WHERE inserted_at > $1 AND inserted_at <= $2"));
+        assert!(sql.contains(
+            "AND date_bin(INTERVAL '1 day', event_timestamp, TIMESTAMPTZ '2000-01-01 00:00:00+00') < $3"
+        ));
+        // Every bucket of a row lies in its day, so no skipped row is left unrecorded
+        for (_, width) in ROLLUPS.iter().chain(SENSOR_ROLLUPS.iter()) {
+            assert!(["5 minutes", "1 hour", "1 day"].contains(width));
+        }
+    }
+
+    #[test]
+    fn sensor_rollup_sql_recomputes_the_buckets_of_late_readings_per_device_and_quantity() {
+        let sql = sensor_rollup_sql("sensor_reading_daily", "1 day");
+        assert!(sql.contains("FROM sensor_reading WHERE inserted_at > $1 AND inserted_at <= $2"));
+        assert!(sql.contains(
+            "JOIN sensor_reading r ON r.device_id = d.device_id AND r.quantity = d.quantity \
+             AND r.event_timestamp >= d.bucket AND r.event_timestamp < d.bucket + INTERVAL '1 day'"
+        ));
+        assert!(sql.contains("WHERE d.bucket >= $3"));
+        assert!(sql.contains("ON CONFLICT (device_id, quantity, bucket) DO UPDATE"));
+    }
+}
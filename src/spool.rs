@@ -0,0 +1,219 @@
+use crate::models::{
+    model_charging::ChargingSession, model_lambda::LambdaData, model_pv::PvData, model_sensor::SensorSample,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A database write that is kept in the spool while PostgreSQL is unavailable
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SpooledWrite {
+    Lambda(Box<LambdaData>),
+    Temperature(SensorSample),
+    Pv(PvData),
+    ChargingSession(ChargingSession),
+}
+
+#[derive(Debug)]
+pub enum SpoolError {
+    IoError(String, std::io::Error),
+    SerializeError(serde_json::Error),
+    Full(u64),
+}
+
+impl fmt::Display for SpoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpoolError::IoError(path, e) => write!(f, "Error accessing spool {}: {}", path, e),
+            SpoolError::SerializeError(e) => write!(f, "Error serializing spooled write: {}", e),
+            SpoolError::Full(max_bytes) => write!(f, "Spool full ({} bytes), sample dropped", max_bytes),
+        }
+    }
+}
+
+impl std::error::Error for SpoolError {}
+
+/// Append-only JSON lines file of writes that failed because the database was unreachable,
+/// replayed oldest first once it is back
+pub struct Spool {
+    path: PathBuf,
+    max_bytes: u64,
+    depth: usize,
+    size: u64,
+}
+
+impl Spool {
+    /// Opens the spool at `path`, entries left by a previous run are kept
+    pub fn open(path: &Path, max_bytes: u64) -> Result<Self, SpoolError> {
+        let mut spool = Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            depth: 0,
+            size: 0,
+        };
+        if path.exists() {
+            spool.depth = spool.entries()?.len();
+            spool.size = fs::metadata(path).map_err(|e| spool.io_error(e))?.len();
+        }
+        Ok(spool)
+    }
+
+    /// Number of spooled writes
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Size of the spool file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn append(&mut self, write: &SpooledWrite) -> Result<(), SpoolError> {
+        let mut line = serde_json::to_string(write).map_err(SpoolError::SerializeError)?;
+        line.push('\n');
+        if self.size + line.len() as u64 > self.max_bytes {
+            return Err(SpoolError::Full(self.max_bytes));
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| self.io_error(e))?;
+        file.write_all(line.as_bytes()).map_err(|e| self.io_error(e))?;
+        // The spool is only useful if it survives the NAS going down with the database
+        file.sync_data().map_err(|e| self.io_error(e))?;
+        self.depth += 1;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Spooled writes as serialized, oldest first
+    pub fn entries(&self) -> Result<Vec<String>, SpoolError> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = File::open(&self.path).map_err(|e| self.io_error(e))?;
+        BufReader::new(file)
+            .lines()
+            .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+            .collect::<Result<_, _>>()
+            .map_err(|e| self.io_error(e))
+    }
+
+    /// Replaces the spool with the entries that are still to be written
+    pub fn retain(&mut self, entries: &[String]) -> Result<(), SpoolError> {
+        if entries.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path).map_err(|e| self.io_error(e))?;
+            }
+            self.depth = 0;
+            self.size = 0;
+            return Ok(());
+        }
+        // Written next to the spool and renamed, so a crash leaves either the old or the new file
+        let temporary = self.path.with_extension("tmp");
+        let content: String = entries.iter().map(|entry| format!("{}\n", entry)).collect();
+        fs::write(&temporary, &content).map_err(|e| self.io_error(e))?;
+        fs::rename(&temporary, &self.path).map_err(|e| self.io_error(e))?;
+        self.depth = entries.len();
+        self.size = content.len() as u64;
+        Ok(())
+    }
+
+    fn io_error(&self, e: std::io::Error) -> SpoolError {
+        SpoolError::IoError(self.path.display().to_string(), e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    // Spool file in the temp directory, removed when the test is done
+    struct TestPath(PathBuf);
+
+    impl TestPath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("fetcher-spool-{}-{}.jsonl", std::process::id(), name));
+            let _ = fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TestPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn pv(minute: i64) -> SpooledWrite {
+        SpooledWrite::Pv(PvData {
+            event_timestamp: DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(minute),
+            clock_skew_ms: 0,
+            battery_power: 0,
+            battery_percentage: 50.0,
+            grid: 0,
+            home: 500,
+            pv: 1000,
+            wallbox: 0,
+        })
+    }
+
+    fn minutes(entries: &[String]) -> Vec<i64> {
+        entries
+            .iter()
+            .map(|entry| match serde_json::from_str(entry).unwrap() {
+                SpooledWrite::Pv(data) => data.event_timestamp.timestamp() / 60,
+                other => panic!("unexpected write {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn entries_are_replayed_in_append_order_across_restarts() {
+        let path = TestPath::new("order");
+        let mut spool = Spool::open(&path.0, 1024 * 1024).unwrap();
+        assert!(spool.entries().unwrap().is_empty());
+        for minute in [3, 1, 2] {
+            spool.append(&pv(minute)).unwrap();
+        }
+        assert_eq!(minutes(&spool.entries().unwrap()), [3, 1, 2]);
+
+        let reopened = Spool::open(&path.0, 1024 * 1024).unwrap();
+        assert_eq!((reopened.depth(), reopened.size()), (3, spool.size()));
+        assert_eq!(minutes(&reopened.entries().unwrap()), [3, 1, 2]);
+    }
+
+    #[test]
+    fn retain_keeps_the_entries_still_to_be_written() {
+        let path = TestPath::new("retain");
+        let mut spool = Spool::open(&path.0, 1024 * 1024).unwrap();
+        for minute in 0..3 {
+            spool.append(&pv(minute)).unwrap();
+        }
+        let entries = spool.entries().unwrap();
+        spool.retain(&entries[1..]).unwrap();
+        assert_eq!(spool.depth(), 2);
+        assert_eq!(minutes(&spool.entries().unwrap()), [1, 2]);
+        assert_eq!(spool.size(), fs::metadata(&path.0).unwrap().len());
+
+        spool.retain(&[]).unwrap();
+        assert!(!path.0.exists());
+        assert_eq!((spool.depth(), spool.size()), (0, 0));
+    }
+
+    #[test]
+    fn append_refuses_writes_beyond_the_size_cap() {
+        let path = TestPath::new("cap");
+        let line = serde_json::to_string(&pv(0)).unwrap().len() as u64 + 1;
+        let mut spool = Spool::open(&path.0, 2 * line).unwrap();
+        spool.append(&pv(0)).unwrap();
+        spool.append(&pv(1)).unwrap();
+        assert!(matches!(spool.append(&pv(2)), Err(SpoolError::Full(max_bytes)) if max_bytes == 2 * line));
+        assert_eq!((spool.depth(), spool.size()), (2, 2 * line));
+        assert_eq!(minutes(&spool.entries().unwrap()), [0, 1]);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::client::IoBrokerClientOptions;
+use crate::models::model_iobroker::{IoBrokerResponse, IoBrokerValue};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::{sleep, timeout};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::{Connector, connect_async_tls_with_config};
+
+/// Latest known value of every subscribed ioBroker state, kept up to date by
+/// [`IoBrokerSubscriptionClient`] and safe to snapshot at any time.
+#[derive(Clone, Default)]
+pub struct StateCache {
+    states: Arc<RwLock<IoBrokerResponse>>,
+    triggered: Arc<Notify>,
+}
+
+impl StateCache {
+    /// Snapshot of all states matching an ioBroker pattern such as `modbus.0.holdingRegisters.*`
+    pub fn snapshot_matching(&self, pattern: &str) -> IoBrokerResponse {
+        self.states
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(id, _)| matches_pattern(id, pattern))
+            .map(|(id, value)| (id.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Snapshot of exactly the given state IDs, missing ones are left out
+    pub fn snapshot_ids(&self, ids: &[&str]) -> IoBrokerResponse {
+        let states = self.states.read().unwrap();
+        ids.iter()
+            .filter_map(|id| states.get(*id).map(|value| (id.to_string(), value.clone())))
+            .collect()
+    }
+
+    /// Resolves when one of the trigger states changed its value
+    pub async fn triggered(&self) {
+        self.triggered.notified().await
+    }
+
+    fn update(&self, id: String, value: Option<IoBrokerValue>) -> bool {
+        let mut states = self.states.write().unwrap();
+        match value {
+            Some(value) => {
+                let changed = states.get(&id).is_none_or(|previous| previous.val != value.val);
+                states.insert(id, value);
+                changed
+            }
+            None => states.remove(&id).is_some(),
+        }
+    }
+
+    fn replace(&self, pattern: &str, values: IoBrokerResponse) {
+        let mut states = self.states.write().unwrap();
+        states.retain(|id, _| !matches_pattern(id, pattern));
+        states.extend(values);
+    }
+}
+
+fn matches_pattern(id: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => id.starts_with(prefix),
+        None => id == pattern,
+    }
+}
+
+/// Subscribes to ioBroker state changes over the socket.io interface of the
+/// web/socketio adapter instead of polling the simple-api.
+pub struct IoBrokerSubscriptionClient {
+    url: String,
+    options: IoBrokerClientOptions,
+    patterns: Vec<String>,
+    trigger_ids: Vec<String>,
+    cache: StateCache,
+    reconnect_delay: Duration,
+    // socket.io pings every 25s by default, silence for longer means the connection is dead
+    read_timeout: Duration,
+}
+
+impl IoBrokerSubscriptionClient {
+    pub fn new(
+        url: String,
+        options: IoBrokerClientOptions,
+        patterns: Vec<String>,
+        trigger_ids: Vec<String>,
+    ) -> Self {
+        Self {
+            url,
+            options,
+            patterns,
+            trigger_ids,
+            cache: StateCache::default(),
+            reconnect_delay: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(60),
+        }
+    }
+
+    pub fn cache(&self) -> StateCache {
+        self.cache.clone()
+    }
+
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.subscribe().await {
+                eprintln!("ioBroker subscription error: {}, reconnecting in {:?}", e, self.reconnect_delay);
+            }
+            sleep(self.reconnect_delay).await;
+        }
+    }
+
+    async fn subscribe(&self) -> Result<(), Box<dyn Error>> {
+        let url = format!("{}/socket.io/?EIO=4&transport=websocket", websocket_url(&self.url));
+        println!("Connecting to ioBroker socket: {}", url);
+        let mut request = url.as_str().into_client_request()?;
+        if let Some(authorization) = self.options.auth.header_value() {
+            request.headers_mut().insert(AUTHORIZATION, authorization.parse()?);
+        }
+        let connector = match url.starts_with("wss://") {
+            true => Some(Connector::NativeTls(self.options.tls.tls_connector()?)),
+            false => None,
+        };
+        let (mut socket, _) = connect_async_tls_with_config(request, None, false, connector).await?;
+        // Ack ids of pending getStates requests, mapped to the pattern they were sent for
+        let mut pending: HashMap<u64, String> = HashMap::new();
+
+        loop {
+            let message = match timeout(self.read_timeout, socket.next()).await {
+                Ok(Some(message)) => message?,
+                Ok(None) => return Err("connection closed by ioBroker".into()),
+                Err(_) => return Err("no message from ioBroker within read timeout".into()),
+            };
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => return Err("connection closed by ioBroker".into()),
+                _ => continue,
+            };
+
+            match parse_packet(&text) {
+                SocketPacket::Open => socket.send(Message::Text("40".to_string())).await?,
+                SocketPacket::Ping => socket.send(Message::Text("3".to_string())).await?,
+                SocketPacket::Connected => {
+                    println!("Connected to ioBroker socket, subscribing to {:?}", self.patterns);
+                    for (ack_id, pattern) in self.patterns.iter().enumerate() {
+                        let subscribe = serde_json::json!(["subscribe", pattern]);
+                        socket.send(Message::Text(format!("42{}", subscribe))).await?;
+                        // Fill the cache with the current values, events only carry changes
+                        let get_states = serde_json::json!(["getStates", pattern]);
+                        socket.send(Message::Text(format!("42{}{}", ack_id, get_states))).await?;
+                        pending.insert(ack_id as u64, pattern.clone());
+                    }
+                }
+                SocketPacket::Event(payload) => self.handle_event(payload),
+                SocketPacket::Ack(ack_id, payload) => {
+                    if let Some(pattern) = pending.remove(&ack_id) {
+                        let states = parse_states(payload);
+                        println!("Received {} states for {}", states.len(), pattern);
+                        self.cache.replace(&pattern, states);
+                    }
+                }
+                SocketPacket::ConnectError(e) => return Err(format!("ioBroker refused connection: {}", e).into()),
+                SocketPacket::Close => return Err("connection closed by ioBroker".into()),
+                SocketPacket::Other => {}
+            }
+        }
+    }
+
+    fn handle_event(&self, payload: Value) {
+        // stateChange events look like ["stateChange", id, state], state is null when deleted
+        let Some([name, id, state]) = payload.as_array().map(Vec::as_slice) else {
+            return;
+        };
+        if name != "stateChange" {
+            return;
+        }
+        let Some(id) = id.as_str() else {
+            return;
+        };
+
+        let value = match state {
+            Value::Null => None,
+            state => match serde_json::from_value::<IoBrokerValue>(state.clone()) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    eprintln!("Error parsing state change for {}: {}", id, e);
+                    return;
+                }
+            },
+        };
+
+        if self.cache.update(id.to_string(), value) && self.trigger_ids.iter().any(|trigger| trigger == id) {
+            println!("Trigger state {} changed", id);
+            self.cache.triggered.notify_one();
+        }
+    }
+}
+
+fn websocket_url(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        url.to_string()
+    }
+}
+
+fn parse_states(payload: Value) -> IoBrokerResponse {
+    // getStates acks carry [error, {id: state}]
+    let Some(Value::Object(states)) = payload.as_array().and_then(|args| args.get(1)) else {
+        return IoBrokerResponse::new();
+    };
+    states
+        .iter()
+        .filter_map(|(id, state)| {
+            serde_json::from_value::<IoBrokerValue>(state.clone())
+                .ok()
+                .map(|value| (id.clone(), value))
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq)]
+enum SocketPacket {
+    Open,
+    Close,
+    Ping,
+    Connected,
+    ConnectError(String),
+    Event(Value),
+    Ack(u64, Value),
+    Other,
+}
+
+// Engine.IO v4 packet type first, then for messages the socket.io packet type,
+// an optional ack id and the JSON payload, e.g. `42["stateChange",...]` or `431[null,{...}]`
+fn parse_packet(text: &str) -> SocketPacket {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('0') => return SocketPacket::Open,
+        Some('1') => return SocketPacket::Close,
+        Some('2') => return SocketPacket::Ping,
+        Some('4') => {}
+        _ => return SocketPacket::Other,
+    }
+
+    let packet_type = chars.next();
+    let rest = chars.as_str();
+    let ack_len = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    let ack_id = rest[..ack_len].parse::<u64>().ok();
+    let payload = serde_json::from_str::<Value>(&rest[ack_len..]).unwrap_or(Value::Null);
+
+    match (packet_type, ack_id) {
+        (Some('0'), _) => SocketPacket::Connected,
+        (Some('2'), _) => SocketPacket::Event(payload),
+        (Some('3'), Some(ack_id)) => SocketPacket::Ack(ack_id, payload),
+        (Some('4'), _) => SocketPacket::ConnectError(payload.to_string()),
+        _ => SocketPacket::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_packet_reads_engine_io_packets() {
+        assert_eq!(parse_packet(r#"0{"sid":"abc","pingInterval":25000}"#), SocketPacket::Open);
+        assert_eq!(parse_packet("1"), SocketPacket::Close);
+        assert_eq!(parse_packet("2"), SocketPacket::Ping);
+        assert_eq!(parse_packet("3"), SocketPacket::Other);
+        assert_eq!(parse_packet(""), SocketPacket::Other);
+    }
+
+    #[test]
+    fn parse_packet_reads_socket_io_messages() {
+        assert_eq!(parse_packet(r#"40{"sid":"abc"}"#), SocketPacket::Connected);
+        assert_eq!(
+            parse_packet(r#"42["stateChange","modbus.0.x",{"val":1,"ts":2}]"#),
+            SocketPacket::Event(json!(["stateChange", "modbus.0.x", {"val": 1, "ts": 2}]))
+        );
+        assert_eq!(
+            parse_packet(r#"4312[null,{"a":{"val":1}}]"#),
+            SocketPacket::Ack(12, json!([null, {"a": {"val": 1}}]))
+        );
+        assert_eq!(
+            parse_packet(r#"44{"message":"not authorized"}"#),
+            SocketPacket::ConnectError(String::from(r#"{"message":"not authorized"}"#))
+        );
+    }
+
+    #[test]
+    fn parse_packet_ignores_acks_without_id_and_unknown_types() {
+        assert_eq!(parse_packet("43[null]"), SocketPacket::Other);
+        assert_eq!(parse_packet("41"), SocketPacket::Other);
+        assert_eq!(parse_packet("6"), SocketPacket::Other);
+        assert_eq!(parse_packet("42not json"), SocketPacket::Event(Value::Null));
+    }
+
+    #[test]
+    fn parse_states_skips_states_that_are_not_values() {
+        let state = json!({"val": 1.5, "ack": true, "ts": 1700000000000_i64, "q": 0, "from": "system.adapter.modbus.0", "user": "system.user.admin", "lc": 1700000000000_i64});
+        let states = parse_states(json!([null, {"a": state, "b": null, "c": {"val": 1}}]));
+        assert_eq!(states.len(), 1);
+        assert!(states.contains_key("a"));
+        assert!(parse_states(json!(["error"])).is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DbErr, Statement};
+
+/// Tables turned into hypertables, with the columns their compressed chunks are segmented by
+const HYPERTABLES: [(&str, Option<&str>); 2] = [
+    ("heatpump", None),
+    ("sensor_reading", Some("device_id, quantity")),
+];
+
+/// Chunks older than this are compressed, late writes from the spool or backfill usually arrive earlier
+const COMPRESS_AFTER: &str = "7 days";
+
+/// Continuous aggregates of the heat pump with their bucket width and how far back they are refreshed
+const HEATPUMP_AGGREGATES: [(&str, &str, &str); 3] = [
+    ("heatpump_5min_agg", "5 minutes", "1 day"),
+    ("heatpump_hourly_agg", "1 hour", "3 days"),
+    ("heatpump_daily_agg", "1 day", "30 days"),
+];
+
+/// Sensors report every 15 minutes, so there are no 5 minute buckets for them
+const SENSOR_AGGREGATES: [(&str, &str, &str); 2] = [
+    ("sensor_reading_hourly_agg", "1 hour", "3 days"),
+    ("sensor_reading_daily_agg", "1 day", "30 days"),
+];
+
+// Energy counters keep their last reading of the bucket, the difference to the previous
+// bucket is the energy of the bucket
+const HEATPUMP_METRICS: &str = r#"
+    count(*) AS samples,
+    avg(ambient_temperaturecalculated) AS ambient_temperature,
+    avg(heatpump_flowlinetemp) AS flowline_temp,
+    avg(heatpump_returnlinetemp) AS return_line_temp,
+    avg(heatpump_energysourceinlettemp) AS energy_source_inlet_temp,
+    avg(heatpump_actualheatingcapacity) AS heating_capacity,
+    avg(heatpump_inverteractualpower) AS inverter_power,
+    avg(heatpump_currentcop) AS cop,
+    avg(boiler_hightemp) AS boiler_high_temp,
+    avg(buffer_hightemp) AS buffer_high_temp,
+    max(heatpump_heatenergy) AS heat_energy,
+    max(heatpump_electricenergy) AS electric_energy
+"#;
+
+/// Turns the sample tables into TimescaleDB hypertables with compression and continuous aggregates.
+/// Every step is skipped when already done, so this runs on every startup.
+/// Returns false without changing anything when the extension is not available.
+pub async fn enable<C: ConnectionTrait>(db: &C) -> Result<bool, DbErr> {
+    if !extension_available(db).await? {
+        return Ok(false);
+    }
+    db.execute_unprepared("CREATE EXTENSION IF NOT EXISTS timescaledb").await?;
+
+    for (table, segment_by) in HYPERTABLES {
+        // The tables already have a btree index on event_timestamp
+        db.execute_unprepared(&format!(
+            "SELECT create_hypertable('{table}', 'event_timestamp', if_not_exists => TRUE, migrate_data => TRUE, create_default_indexes => FALSE)"
+        ))
+        .await?;
+        // The settings cannot be changed once chunks are compressed
+        if !compression_enabled(db, table).await? {
+            let segment_by = segment_by
+                .map(|columns| format!(", timescaledb.compress_segmentby = '{columns}'"))
+                .unwrap_or_default();
+            db.execute_unprepared(&format!(
+                "ALTER TABLE {table} SET (timescaledb.compress, timescaledb.compress_orderby = 'event_timestamp DESC'{segment_by})"
+            ))
+            .await?;
+        }
+        db.execute_unprepared(&format!(
+            "SELECT add_compression_policy('{table}', INTERVAL '{COMPRESS_AFTER}', if_not_exists => TRUE)"
+        ))
+        .await?;
+    }
+
+    for (name, bucket, refresh) in HEATPUMP_AGGREGATES {
+        let query = format!(
+            "SELECT time_bucket(INTERVAL '{bucket}', event_timestamp) AS bucket,{HEATPUMP_METRICS}FROM heatpump GROUP BY 1"
+        );
+        create_aggregate(db, name, &query, bucket, refresh).await?;
+    }
+    for (name, bucket, refresh) in SENSOR_AGGREGATES {
+        let query = format!(
+            "SELECT time_bucket(INTERVAL '{bucket}', event_timestamp) AS bucket, device_id, quantity, \
+             avg(value) AS value, min(value) AS min_value, max(value) AS max_value \
+             FROM sensor_reading GROUP BY 1, device_id, quantity"
+        );
+        create_aggregate(db, name, &query, bucket, refresh).await?;
+    }
+    Ok(true)
+}
+
+async fn create_aggregate<C: ConnectionTrait>(
+    db: &C,
+    name: &str,
+    query: &str,
+    bucket: &str,
+    refresh: &str,
+) -> Result<(), DbErr> {
+    db.execute_unprepared(&format!(
+        "CREATE MATERIALIZED VIEW IF NOT EXISTS {name} WITH (timescaledb.continuous) AS {query} WITH NO DATA"
+    ))
+    .await?;
+    // The newest bucket is still filling up, it is refreshed once it is complete
+    db.execute_unprepared(&format!(
+        "SELECT add_continuous_aggregate_policy('{name}', start_offset => INTERVAL '{refresh}', \
+         end_offset => INTERVAL '{bucket}', schedule_interval => INTERVAL '{bucket}', if_not_exists => TRUE)"
+    ))
+    .await?;
+    db.execute_unprepared(&format!("GRANT SELECT ON {name} TO fetcher")).await?;
+    Ok(())
+}
+
+async fn extension_available<C: ConnectionTrait>(db: &C) -> Result<bool, DbErr> {
+    let statement = Statement::from_string(
+        DatabaseBackend::Postgres,
+        "SELECT EXISTS (SELECT FROM pg_available_extensions WHERE name = 'timescaledb') AS available",
+    );
+    match db.query_one(statement).await? {
+        Some(row) => row.try_get("", "available"),
+        None => Ok(false),
+    }
+}
+
+async fn compression_enabled<C: ConnectionTrait>(db: &C, table: &str) -> Result<bool, DbErr> {
+    let statement = Statement::from_sql_and_values(
+        DatabaseBackend::Postgres,
+        "SELECT compression_enabled FROM timescaledb_information.hypertables WHERE hypertable_name = $1",
+        [table.into()],
+    );
+    match db.query_one(statement).await? {
+        Some(row) => row.try_get("", "compression_enabled"),
+        None => Ok(false),
+    }
+}